@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Failure, Result, Warning};
+use crate::file_type::FileTypeFactory;
+use crate::Generator;
+
+// Settings for generating a site from `source` into `dest`, e.g.
+// `Builder::new("site", "out").base_url("notes").build()`
+pub struct Builder {
+    pub(crate) source: PathBuf,
+    pub(crate) dest: PathBuf,
+    pub(crate) base_url: Option<String>,
+    // Anything left unset comes from the config file
+    pub(crate) clean: Option<bool>,
+    pub(crate) jobs: Option<usize>,
+    pub(crate) fail_fast: Option<bool>,
+    pub(crate) factories: Vec<Box<dyn FileTypeFactory>>,
+}
+
+impl Builder {
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(source: P, dest: Q) -> Builder {
+        Builder {
+            source: PathBuf::from(source.as_ref()),
+            dest: PathBuf::from(dest.as_ref()),
+            base_url: None,
+            clean: None,
+            jobs: None,
+            fail_fast: None,
+            factories: Vec::new(),
+        }
+    }
+
+    // Path every url starts with, without the hostname. Defaults to
+    // `base_url` from `rust-notes.toml`, or `/`.
+    pub fn base_url(mut self, base_url: &str) -> Builder {
+        self.base_url = Some(String::from(base_url));
+        self
+    }
+
+    // Empty dest and rebuild everything instead of only what changed
+    pub fn clean(mut self, clean: bool) -> Builder {
+        self.clean = Some(clean);
+        self
+    }
+
+    // Number of files to convert in parallel
+    pub fn jobs(mut self, jobs: usize) -> Builder {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    // Stop at the first file that fails instead of reporting it at the end
+    pub fn fail_fast(mut self, fail_fast: bool) -> Builder {
+        self.fail_fast = Some(fail_fast);
+        self
+    }
+
+    // Adds a file type. Factories are tried in the order they were added and
+    // before the built in markdown and directory types, so they can take
+    // over any path.
+    pub fn factory<F: FileTypeFactory + 'static>(mut self, factory: F) -> Builder {
+        self.factories.push(Box::new(factory));
+        self
+    }
+
+    // Sets up a generator without building anything yet, for callers that
+    // want to rebuild later, e.g. to watch or serve the site
+    pub fn generator<'a>(self) -> Result<Generator<'a>> {
+        Generator::new(self)
+    }
+
+    pub fn build(self) -> Result<Report> {
+        self.generator()?.build()
+    }
+}
+
+// What a build did. Outputs are relative to dest.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub written: Vec<PathBuf>,
+    // Outputs left alone because their inputs are the same as last build
+    pub unchanged: usize,
+    pub removed: Vec<PathBuf>,
+    pub failures: Vec<Failure>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Report {
+    // Turns a build where files failed into `Error::Build`
+    pub fn into_result(self) -> Result<Report> {
+        if self.failures.is_empty() {
+            Ok(self)
+        } else {
+            Err(Error::Build(self.failures))
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{Event, Parser, Tag};
+
+use crate::error::{Error, Result};
+use crate::file_type::read_file;
+use crate::front_matter;
+use crate::links::{self, is_relative};
+use crate::util::percent_decode;
+use crate::{AppContext, Generator};
+
+// A link or image in a note that doesn't lead to anything in dest
+#[derive(Debug)]
+pub struct BrokenLink {
+    pub path: PathBuf,
+    // 1 based, counting the front matter
+    pub line: usize,
+    // As written in the note
+    pub href: String,
+}
+
+impl fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.href)
+    }
+}
+
+impl<'a> Generator<'a> {
+    // Checks every link and image in the notes of the last build against
+    // what's in dest, failing with `Error::BrokenLinks` if any lead nowhere.
+    // Links to other sites aren't checked.
+    pub fn check(&self) -> Result<()> {
+        let mut broken = Vec::new();
+        for note in self.context.index.notes.iter() {
+            broken.extend(check_note(&self.context, &note.path, &note.url));
+        }
+        if broken.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::BrokenLinks(broken))
+        }
+    }
+}
+
+fn check_note(context: &AppContext<'_>, path: &Path, url: &str) -> Vec<BrokenLink> {
+    // Notes that can't be read or parsed already failed to build
+    let contents = match read_file(path) {
+        Ok(contents) => contents,
+        Err(_) => return Vec::new(),
+    };
+    let (meta, body) = match front_matter::split(&contents) {
+        Ok(split) => split,
+        Err(_) => return Vec::new(),
+    };
+    let options = context.config.markdown.options(&meta);
+    let body_start = body.as_ptr() as usize - contents.as_ptr() as usize;
+    let line = |offset: usize| {
+        contents[..body_start + offset]
+            .bytes()
+            .filter(|&b| b == b'\n')
+            .count()
+            + 1
+    };
+
+    let mut broken = Vec::new();
+    for (event, range) in Parser::new_ext(body, options).into_offset_iter() {
+        let href = match event {
+            Event::Start(Tag::Link(_, href, _)) | Event::Start(Tag::Image(_, href, _)) => href,
+            _ => continue,
+        };
+        // Checked as rendered, so relative links to sources are followed to
+        // their output
+        let rendered = if is_relative(&href) {
+            links::source_url(context, path, &href)
+        } else {
+            None
+        };
+        let exists = match rendered {
+            Some(ref rendered) => leads_somewhere(context, url, rendered),
+            None => leads_somewhere(context, url, &href),
+        };
+        if !exists {
+            broken.push(BrokenLink {
+                path: PathBuf::from(path),
+                line: line(range.start),
+                href: href.to_string(),
+            });
+        }
+    }
+    for (target, offset) in links::wiki_targets_at(body, options) {
+        if context.index.resolve_url(path, &target).is_none() {
+            broken.push(BrokenLink {
+                path: PathBuf::from(path),
+                line: line(offset),
+                href: format!("[[{}]]", target),
+            });
+        }
+    }
+    broken.sort_by_key(|link| link.line);
+    broken
+}
+
+// Whether `href` on the page at `page_url` names a file in dest. Anything
+// outside the base url, or on another site, is assumed to.
+fn leads_somewhere(context: &AppContext<'_>, page_url: &str, href: &str) -> bool {
+    let end = href.find(['?', '#']).unwrap_or(href.len());
+    let href = &href[..end];
+    if href.is_empty() {
+        return true;
+    }
+    let url = if href.starts_with('/') && !href.starts_with("//") {
+        String::from(href)
+    } else if is_relative(href) {
+        let dir = &page_url[..page_url.rfind('/').map_or(0, |slash| slash + 1)];
+        format!("{}{}", dir, href)
+    } else {
+        return true;
+    };
+    let url = percent_decode(&url);
+    let relative = match url.strip_prefix(context.base_url.as_str()) {
+        Some(relative) => relative,
+        None if format!("{}/", url) == context.base_url => "",
+        None => return true,
+    };
+    // `..` past the base url leaves the site too
+    let mut parts: Vec<&str> = Vec::new();
+    for part in relative.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                if parts.pop().is_none() {
+                    return true;
+                }
+            }
+            part => parts.push(part),
+        }
+    }
+    let mut dest = context.root_dest.clone();
+    dest.extend(parts);
+    dest.is_file() || dest.join("index.html").is_file()
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{SecondsFormat, Utc};
+use pulldown_cmark::Options;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+use crate::front_matter::{self, Meta};
+
+pub static CONFIG_FILE: &str = "rust-notes.toml";
+
+// Settings from `rust-notes.toml` at the source root, with anything given to
+// the `Builder` applied on top. Templates see all of it as `config`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub base_url: String,
+    // Scheme and host the site is served from, e.g. `https://example.com`,
+    // for pages that need absolute urls. Feeds are only written with one.
+    pub hostname: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    // Anything else templates should have, as `site.extra`
+    #[serde(deserialize_with = "deserialize_extra")]
+    pub extra: Meta,
+    pub output: Output,
+    pub feed: Feed,
+    pub highlight: Highlight,
+    pub markdown: Markdown,
+    pub listing: Listing,
+    // Paths relative to the source root that are left out of the site,
+    // along with everything below them
+    pub ignore: Vec<PathBuf>,
+    // Patterns in gitignore syntax for more of the source to leave out, like
+    // `.notesignore` at the source root has
+    pub exclude: Vec<String>,
+    // Whether files and directories starting with a dot are part of the site
+    pub hidden: bool,
+    pub templates: Templates,
+    // Switches that templates can check, e.g. `{{#if config.features.comments}}`
+    pub features: BTreeMap<String, bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Output {
+    pub clean: bool,
+    pub jobs: usize,
+    pub fail_fast: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Feed {
+    // How many of the most recent notes feeds list, 0 for no feeds
+    pub limit: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Highlight {
+    pub enabled: bool,
+    // A syntect theme name or a `.tmTheme` file relative to the source root
+    pub theme: String,
+}
+
+// CommonMark extensions. Notes can turn each on or off for themselves in
+// their front matter, e.g. `markdown: { smart_punctuation: true }`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Markdown {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub tasklists: bool,
+    pub smart_punctuation: bool,
+    pub math: bool,
+}
+
+// How directory pages order what's in them. A directory's `.listing.toml`
+// can change any of it for itself.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Listing {
+    pub sort: Sort,
+    // Flips the order `sort` gives
+    pub reverse: bool,
+    // Directories before everything else
+    pub dirs_first: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sort {
+    // By file stem, byte for byte
+    Name,
+    // By file stem, with numbers compared as numbers
+    Natural,
+    CaseInsensitive,
+    // Oldest first, by front matter date or when the file was last modified
+    Date,
+    // By `weight` in front matter, lowest first, then anything without one
+    Weight,
+}
+
+// Template directories, relative to the source root
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Templates {
+    pub layouts: PathBuf,
+    pub partials: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            base_url: String::from("/"),
+            hostname: None,
+            title: None,
+            description: None,
+            author: None,
+            extra: Meta::new(),
+            output: Output::default(),
+            feed: Feed::default(),
+            highlight: Highlight::default(),
+            markdown: Markdown::default(),
+            listing: Listing::default(),
+            ignore: Vec::new(),
+            exclude: Vec::new(),
+            hidden: false,
+            templates: Templates::default(),
+            features: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for Output {
+    fn default() -> Output {
+        Output {
+            clean: false,
+            jobs: 1,
+            fail_fast: false,
+        }
+    }
+}
+
+impl Default for Feed {
+    fn default() -> Feed {
+        Feed { limit: 20 }
+    }
+}
+
+impl Default for Highlight {
+    fn default() -> Highlight {
+        Highlight {
+            enabled: true,
+            theme: String::from("InspiredGitHub"),
+        }
+    }
+}
+
+impl Default for Markdown {
+    fn default() -> Markdown {
+        Markdown {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            tasklists: true,
+            smart_punctuation: false,
+            math: true,
+        }
+    }
+}
+
+impl Markdown {
+    // Parser options for a note with `meta` as its front matter
+    pub fn options(&self, meta: &Meta) -> Options {
+        let mut options = Options::empty();
+        for &(key, default, option) in [
+            ("tables", self.tables, Options::ENABLE_TABLES),
+            ("footnotes", self.footnotes, Options::ENABLE_FOOTNOTES),
+            (
+                "strikethrough",
+                self.strikethrough,
+                Options::ENABLE_STRIKETHROUGH,
+            ),
+            ("tasklists", self.tasklists, Options::ENABLE_TASKLISTS),
+            (
+                "smart_punctuation",
+                self.smart_punctuation,
+                Options::ENABLE_SMART_PUNCTUATION,
+            ),
+        ]
+        .iter()
+        {
+            options.set(option, enabled(meta, key, default));
+        }
+        options
+    }
+
+    // Whether `$formulas$` in a note with `meta` as its front matter are
+    // rendered as MathML
+    pub fn math(&self, meta: &Meta) -> bool {
+        enabled(meta, "math", self.math)
+    }
+}
+
+// A switch from the note's `markdown` front matter, or else the config's
+fn enabled(meta: &Meta, key: &str, default: bool) -> bool {
+    meta.get("markdown")
+        .and_then(|overrides| overrides.get(key))
+        .and_then(Value::as_bool)
+        .unwrap_or(default)
+}
+
+impl Default for Listing {
+    fn default() -> Listing {
+        Listing {
+            sort: Sort::Name,
+            reverse: false,
+            dirs_first: true,
+        }
+    }
+}
+
+impl Default for Templates {
+    fn default() -> Templates {
+        Templates {
+            layouts: PathBuf::from("layouts"),
+            partials: PathBuf::from("partials"),
+        }
+    }
+}
+
+impl Config {
+    // Reads the config of the site at `source`. Sites without one get the
+    // defaults.
+    pub fn load(source: &Path) -> Result<Config> {
+        let path = source.join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let contents = fs::read_to_string(&path).map_err(Error::io("Could not read", &path))?;
+        let mut config: Config = toml::from_str(&contents).map_err(|why| Error::Config {
+            path: path.clone(),
+            message: why.to_string(),
+        })?;
+        config.base_url = normalize_base_url(&config.base_url);
+        if let Some(ref mut hostname) = config.hostname {
+            *hostname = String::from(hostname.trim_end_matches('/'));
+        }
+        Ok(config)
+    }
+}
+
+// Site wide values every page model has as `site`
+#[derive(Serialize)]
+pub struct Site {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    // When the page was rendered, as RFC 3339 in UTC
+    pub built: String,
+    pub generator: String,
+    pub extra: Meta,
+}
+
+impl Site {
+    pub fn new(config: &Config) -> Site {
+        Site {
+            title: config.title.clone(),
+            description: config.description.clone(),
+            author: config.author.clone(),
+            built: now(),
+            generator: format!("rust-notes {}", env!("CARGO_PKG_VERSION")),
+            extra: config.extra.clone(),
+        }
+    }
+
+    pub fn touch(&mut self) {
+        self.built = now();
+    }
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+// Keeps TOML datetimes as the string that was written, like front matter
+fn deserialize_extra<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Meta, D::Error> {
+    let table = toml::value::Table::deserialize(deserializer)?;
+    Ok(table
+        .into_iter()
+        .map(|(key, value)| (key, front_matter::toml_to_json(value)))
+        .collect())
+}
+
+// Gives the base url a leading and trailing slash, e.g. `notes` is `/notes/`
+pub fn normalize_base_url(base_url: &str) -> String {
+    let trimmed = base_url.trim_matches('/');
+    if trimmed.is_empty() {
+        String::from("/")
+    } else {
+        format!("/{}/", trimmed)
+    }
+}
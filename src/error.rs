@@ -0,0 +1,180 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::check::BrokenLink;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    // The source directory is missing or lacks something every site needs
+    InvalidSource {
+        path: PathBuf,
+        reason: &'static str,
+    },
+    Io {
+        path: PathBuf,
+        context: &'static str,
+        source: io::Error,
+    },
+    FrontMatter {
+        path: PathBuf,
+        message: String,
+    },
+    Config {
+        path: PathBuf,
+        message: String,
+    },
+    Template {
+        name: String,
+        source: Box<handlebars::TemplateError>,
+    },
+    Render {
+        path: PathBuf,
+        source: Box<handlebars::RenderError>,
+    },
+    Watch(notify::Error),
+    Serve {
+        address: String,
+        message: String,
+    },
+    // Files that failed in a build that kept going past them
+    Build(Vec<Failure>),
+    BrokenLinks(Vec<BrokenLink>),
+}
+
+#[derive(Debug)]
+pub struct Failure {
+    // Source file being built, messages carry their own paths
+    pub path: PathBuf,
+    pub error: Error,
+}
+
+// Something worth telling the author about that didn't stop the file from
+// building, e.g. a link to a note that doesn't exist
+#[derive(Debug)]
+pub struct Warning {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl Error {
+    // Wraps an io error with what was being done to which path, for use
+    // with `map_err`
+    pub fn io<P: AsRef<Path>>(context: &'static str, path: P) -> impl FnOnce(io::Error) -> Error {
+        let path = PathBuf::from(path.as_ref());
+        move |source| Error::Io {
+            path,
+            context,
+            source,
+        }
+    }
+
+    // Distinct per kind of failure so scripts can tell them apart. 1 is left
+    // for panics and 2 for usage errors reported by docopt.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::InvalidSource { .. } => 3,
+            Error::Io { .. } => 4,
+            Error::FrontMatter { .. } => 5,
+            Error::Template { .. } => 6,
+            Error::Render { .. } => 7,
+            Error::Watch(_) | Error::Serve { .. } => 8,
+            Error::Build(_) => 9,
+            Error::Config { .. } => 10,
+            Error::BrokenLinks(_) => 11,
+        }
+    }
+
+    // Which step of building a file went wrong, for the failure summary
+    pub fn stage(&self) -> &'static str {
+        match *self {
+            Error::InvalidSource { .. } => "setup",
+            Error::Io { .. } => "io",
+            Error::FrontMatter { .. } => "front matter",
+            Error::Config { .. } => "config",
+            Error::Template { .. } => "template",
+            Error::Render { .. } => "render",
+            Error::Watch(_) => "watch",
+            Error::Serve { .. } => "serve",
+            Error::Build(_) => "build",
+            Error::BrokenLinks(_) => "check",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::InvalidSource { ref path, reason } => {
+                write!(f, "{}: {}", path.display(), reason)
+            }
+            Error::Io {
+                ref path,
+                context,
+                ref source,
+            } => write!(f, "{} {}: {}", context, path.display(), source),
+            Error::FrontMatter {
+                ref path,
+                ref message,
+            } => write!(f, "{}: {}", path.display(), message),
+            Error::Config {
+                ref path,
+                ref message,
+            } => write!(f, "Invalid config {}: {}", path.display(), message),
+            Error::Template {
+                ref name,
+                ref source,
+            } => write!(f, "Could not compile {} template: {}", name, source),
+            Error::Render {
+                ref path,
+                ref source,
+            } => write!(f, "Could not render {}: {}", path.display(), source),
+            Error::Watch(ref source) => write!(f, "Could not watch for changes: {}", source),
+            Error::Serve {
+                ref address,
+                ref message,
+            } => write!(f, "Could not serve on {}: {}", address, message),
+            Error::Build(ref failures) => {
+                write!(f, "{} file(s) failed to build", failures.len())?;
+                for failure in failures {
+                    write!(f, "\n  {} error: {}", failure.error.stage(), failure.error)?;
+                }
+                Ok(())
+            }
+            Error::BrokenLinks(ref links) => {
+                write!(f, "{} broken link(s)", links.len())?;
+                for link in links {
+                    write!(f, "\n  {}", link)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io { ref source, .. } => Some(source),
+            Error::Template { ref source, .. } => Some(source.as_ref()),
+            Error::Render { ref source, .. } => Some(source.as_ref()),
+            Error::Watch(ref source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(source: notify::Error) -> Error {
+        Error::Watch(source)
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::{Component, Path};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::config::{Config, CONFIG_FILE};
+use crate::error::{Error, Result};
+
+// Patterns in gitignore syntax for what to leave out of the site, at the
+// source root
+pub static IGNORE_FILE: &str = ".notesignore";
+
+// Matches paths relative to `source` against its `.notesignore` and the
+// config's `exclude` patterns
+pub fn matcher(source: &Path, config: &Config) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(source);
+    let ignore_file = source.join(IGNORE_FILE);
+    if ignore_file.is_file() {
+        if let Some(why) = builder.add(&ignore_file) {
+            return Err(Error::Config {
+                path: ignore_file,
+                message: why.to_string(),
+            });
+        }
+    }
+    for pattern in config.exclude.iter() {
+        builder
+            .add_line(None, pattern)
+            .map_err(|why| Error::Config {
+                path: source.join(CONFIG_FILE),
+                message: format!("Invalid exclude pattern {:?}: {}", pattern, why),
+            })?;
+    }
+    builder.build().map_err(|why| Error::Config {
+        path: source.join(CONFIG_FILE),
+        message: why.to_string(),
+    })
+}
+
+// Whether a component of `relative` starts with a dot, like `.git` or
+// `.DS_Store`
+pub fn is_hidden(relative: &Path) -> bool {
+    relative.components().any(|component| match component {
+        Component::Normal(name) => name.to_str().is_some_and(|name| name.starts_with('.')),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestSite;
+
+    #[test]
+    fn hidden_paths() {
+        assert!(is_hidden(Path::new(".git")));
+        assert!(is_hidden(Path::new("notes/.drafts/a.md")));
+        assert!(is_hidden(Path::new("assets/.DS_Store")));
+        assert!(!is_hidden(Path::new("notes/a.md")));
+        assert!(!is_hidden(Path::new("./notes/a.md")));
+        assert!(!is_hidden(Path::new("../site/notes/a.md")));
+    }
+
+    #[test]
+    fn notesignore_and_exclude_patterns() {
+        let site = TestSite::new(
+            "exclude-matcher",
+            &[(
+                IGNORE_FILE,
+                "# Comment\n*.swp\nnotes/scratch/**\n!keep.swp\n",
+            )],
+        );
+        let config = Config {
+            exclude: vec![String::from("node_modules/"), String::from("/assets/*.psd")],
+            ..Config::default()
+        };
+        let matcher = matcher(&site.source(), &config).unwrap();
+        let ignored = |path: &str, is_dir: bool| {
+            matcher
+                .matched_path_or_any_parents(path, is_dir)
+                .is_ignore()
+        };
+        assert!(ignored("notes/a.md.swp", false));
+        assert!(!ignored("notes/keep.swp", false));
+        assert!(ignored("notes/scratch/a.md", false));
+        assert!(ignored("assets/node_modules", true));
+        assert!(ignored("assets/node_modules/lib/a.js", false));
+        assert!(!ignored("assets/node_modules", false));
+        assert!(ignored("assets/logo.psd", false));
+        assert!(!ignored("assets/img/logo.psd", false));
+        assert!(!ignored("notes/a.md", false));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        let site = TestSite::new("exclude-invalid", &[]);
+        let config = Config {
+            exclude: vec![String::from("notes/[z-a]")],
+            ..Config::default()
+        };
+        match matcher(&site.source(), &config) {
+            Err(Error::Config { message, .. }) => {
+                assert!(message.contains("notes/[z-a]"), "{}", message)
+            }
+            _ => panic!("Expected a config error"),
+        }
+    }
+}
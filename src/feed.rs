@@ -0,0 +1,163 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::file_type::{read_file, register_template, render_summary};
+use crate::front_matter;
+use crate::index::IndexedNote;
+use crate::{AppContext, Generated};
+
+static RSS_TYPE_STR: &str = "rss";
+static ATOM_TYPE_STR: &str = "atom";
+
+// Written to the root of dest, also their manifest keys
+pub static RSS_FILE: &str = "feed.xml";
+pub static ATOM_FILE: &str = "atom.xml";
+
+// Feeds aren't themed, so their templates are built in. Handlebars escapes
+// for HTML, which is also valid XML.
+static RSS_TEMPLATE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+<channel>
+<title>{{title}}</title>
+<link>{{link}}</link>
+<description>{{description}}</description>
+<atom:link href="{{self_url}}" rel="self" type="application/rss+xml"/>
+<generator>{{site.generator}}</generator>
+{{#if entries}}<lastBuildDate>{{updated_rfc2822}}</lastBuildDate>
+{{/if}}{{#each entries}}<item>
+<title>{{title}}</title>
+<link>{{url}}</link>
+<guid>{{url}}</guid>
+<pubDate>{{updated_rfc2822}}</pubDate>
+<description>{{summary}}</description>
+</item>
+{{/each}}</channel>
+</rss>
+"#;
+
+static ATOM_TEMPLATE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>{{title}}</title>
+<subtitle>{{description}}</subtitle>
+<link href="{{link}}"/>
+<link href="{{self_url}}" rel="self"/>
+<id>{{link}}</id>
+<updated>{{updated}}</updated>
+<author><name>{{#if site.author}}{{site.author}}{{else}}{{title}}{{/if}}</name></author>
+<generator>{{site.generator}}</generator>
+{{#each entries}}<entry>
+<title>{{title}}</title>
+<link href="{{url}}"/>
+<id>{{url}}</id>
+<updated>{{updated}}</updated>
+<summary type="html">{{summary}}</summary>
+</entry>
+{{/each}}</feed>
+"#;
+
+pub fn initialize(app_context: &mut AppContext<'_>) -> Result<()> {
+    register_template(app_context, RSS_TYPE_STR, String::from(RSS_TEMPLATE))?;
+    register_template(app_context, ATOM_TYPE_STR, String::from(ATOM_TEMPLATE))
+}
+
+// Both feeds, listing the most recent notes first. Sites without a hostname
+// don't get any, since feeds need absolute urls.
+pub fn pages(context: &AppContext<'_>) -> Vec<Generated> {
+    let hostname = match context.config.hostname {
+        Some(ref hostname) if context.config.feed.limit > 0 => hostname,
+        _ => return Vec::new(),
+    };
+    let mut notes: Vec<&IndexedNote> = context
+        .index
+        .notes
+        .iter()
+        .filter(|note| !note.private)
+        .collect();
+    notes.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.url.cmp(&b.url)));
+    notes.truncate(context.config.feed.limit);
+
+    let title = context
+        .config
+        .title
+        .clone()
+        .unwrap_or_else(|| hostname.clone());
+    let link = format!("{}{}", hostname, context.base_url);
+    let entries: Vec<Entry> = notes
+        .iter()
+        .map(|note| Entry {
+            title: note.title.clone().unwrap_or_else(|| note.name.clone()),
+            url: format!("{}{}", hostname, note.url),
+            updated: rfc3339(&note.date),
+            updated_rfc2822: note.date.to_rfc2822(),
+            summary: summary(context, note, hostname),
+        })
+        .collect();
+    // An empty feed was last updated whenever, so it doesn't change per build
+    let updated = notes
+        .first()
+        .map_or_else(DateTime::<Utc>::default, |note| note.date);
+
+    [(RSS_TYPE_STR, RSS_FILE), (ATOM_TYPE_STR, ATOM_FILE)]
+        .iter()
+        .map(|&(type_str, file)| {
+            let model = FeedModel {
+                title: title.clone(),
+                description: context
+                    .config
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| title.clone()),
+                link: link.clone(),
+                self_url: format!("{}{}", link, file),
+                updated: rfc3339(&updated),
+                updated_rfc2822: updated.to_rfc2822(),
+                entries: &entries,
+            };
+            Generated {
+                key: String::from(file),
+                output: PathBuf::from(file),
+                type_str,
+                model: serde_json::to_value(model).expect("Could not serialize feed"),
+            }
+        })
+        .collect()
+}
+
+fn summary(context: &AppContext<'_>, note: &IndexedNote, hostname: &str) -> String {
+    let contents = match read_file(&note.path) {
+        Ok(contents) => contents,
+        Err(_) => return String::new(),
+    };
+    match front_matter::split(&contents) {
+        Ok((meta, body)) => render_summary(context, &note.path, &meta, body, hostname),
+        Err(_) => String::new(),
+    }
+}
+
+fn rfc3339(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[derive(Serialize)]
+struct FeedModel<'e> {
+    title: String,
+    description: String,
+    // Absolute url of the site
+    link: String,
+    self_url: String,
+    updated: String,
+    updated_rfc2822: String,
+    entries: &'e [Entry],
+}
+
+#[derive(Serialize)]
+struct Entry {
+    title: String,
+    url: String,
+    updated: String,
+    updated_rfc2822: String,
+    summary: String,
+}
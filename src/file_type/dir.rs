@@ -1,4 +1,3 @@
-use std::cmp::Ordering;
 use std::fs;
 use std::fs::{metadata, File};
 use std::io::Write;
@@ -6,163 +5,208 @@ use std::path::{Path, PathBuf};
 
 use serde::Serialize;
 
-use crate::file_type::{create_parent_links, read_file, FileType, Link};
-use crate::util::RelativeFrom;
+use crate::error::{Error, Result};
+use crate::file_type::markdown::{links_fingerprint, render_content};
+use crate::file_type::{
+    create_parent_links, read_file, read_layout, read_partial, register_template, Common, FileType,
+    Link,
+};
+use crate::front_matter;
+use crate::index::Node;
+use crate::listing;
+use crate::util::{hash_file, hash_str, RelativeFrom};
 
-static TYPE_STR: &'static str = "dir";
+static TYPE_STR: &str = "dir";
+
+// Notes that introduce their directory instead of having a page, first
+// match wins
+static INTRO_FILES: [&str; 2] = ["index.md", "README.md"];
 
 pub struct DirFactory;
 
 impl crate::file_type::FileTypeFactory for DirFactory {
-    fn try_create(&self, path: &Path) -> Option<Box<dyn FileType>> {
-        if metadata(&path)
-            .ok()
-            .expect("Error fetching metadata for file")
-            .is_dir()
-        {
-            Some(Box::new(Dir {
+    fn try_create(&self, path: &Path) -> Result<Option<Box<dyn FileType>>> {
+        let path_metadata =
+            metadata(path).map_err(Error::io("Could not read metadata of", path))?;
+        if path_metadata.is_dir() {
+            Ok(Some(Box::new(Dir {
                 path: PathBuf::from(path),
                 type_str: TYPE_STR,
-                file_type_manager: crate::file_type::FileTypeManager::new(),
-            }))
+            })))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn initialize(&self, app_context: &mut crate::AppContext<'_>) -> Result<(), &'static str> {
-        // Validate generic stuff
-        let header_hbs_path = app_context.root_source.join("partials/header.hbs");
-        if !metadata(&header_hbs_path).is_ok() {
-            return Err("Missing partials/header.hbs");
-        }
-
-        let footer_hbs_path = app_context.root_source.join("partials/footer.hbs");
-        if !metadata(&footer_hbs_path).is_ok() {
-            return Err("Missing partials/footer.hbs");
-        }
-
-        // Validate Dir
-        let dir_hbs_path = app_context.root_source.join("layouts/dir.hbs");
-        if !metadata(&dir_hbs_path).is_ok() {
-            return Err("Missing /layouts/dir.hbs");
-        }
-
+    fn initialize(&self, app_context: &mut crate::AppContext<'_>) -> Result<()> {
         // Grab generic stuff
-        let header_hbs_contents = read_file(header_hbs_path)?;
-        let footer_hbs_contents = read_file(footer_hbs_path)?;
+        let header_hbs_contents = read_partial(app_context, "header.hbs")?;
+        let footer_hbs_contents = read_partial(app_context, "footer.hbs")?;
 
         // Create Dir
         let dir_template_name = TYPE_STR;
-        let dir_hbs_contents = read_file(&dir_hbs_path)?;
-
-        app_context
-            .handlebars
-            .register_template_string(
-                dir_template_name,
-                format!(
-                    "{}\n{}\n{}",
-                    header_hbs_contents, dir_hbs_contents, footer_hbs_contents
-                ),
-            )
-            .ok()
-            .expect("Error registering header|dir|footer template");
-
-        Ok(())
+        let dir_hbs_contents = read_layout(app_context, "dir.hbs")?;
+
+        register_template(
+            app_context,
+            dir_template_name,
+            format!(
+                "{}\n{}\n{}",
+                header_hbs_contents, dir_hbs_contents, footer_hbs_contents
+            ),
+        )
     }
 }
 
 pub struct Dir {
     path: PathBuf,
     type_str: &'static str,
-    file_type_manager: crate::file_type::FileTypeManager,
 }
 
 impl Dir {
+    // Listed from the site index, which already knows every child's url
+    // and type, in the order the config or the directory's listing file asks
     fn get_children(&self, context: &crate::AppContext<'_>) -> Vec<Child> {
-        let mut result: Vec<Child> = Vec::new();
-
-        match fs::read_dir(&self.path) {
-            Ok(items) => {
-                for item in items {
-                    let item = item.unwrap().path();
-                    let child = self.file_type_manager.create_file_type(&item);
-                    result.push(Child {
-                        name: String::from(item.file_stem().unwrap().to_str().unwrap()),
-                        url: child.get_url(context),
-                        file_type: String::from(child.get_type_str()),
-                    });
-                }
-            }
-            Err(_) => (),
-        }
-
-        (&mut result).sort_by(|a, b| {
-            if a.file_type == String::from(TYPE_STR) && b.file_type != String::from(TYPE_STR) {
-                Ordering::Less
-            } else if a.file_type != String::from(TYPE_STR) && b.file_type == String::from(TYPE_STR)
-            {
-                Ordering::Greater
-            } else {
-                a.name.cmp(&b.name)
-            }
-        });
+        let intro = intro(context, &self.path);
+        let mut children: Vec<&Node> = context
+            .index
+            .children(&self.path)
+            .filter(|(path, _)| Some(*path) != intro.as_ref())
+            .map(|(_, node)| node)
+            .collect();
+        let own = context
+            .index
+            .node(&self.path)
+            .and_then(|node| node.listing.as_ref());
+        listing::sort(&mut children, &context.config.listing, own);
+
+        children
+            .into_iter()
+            .map(|child| Child {
+                name: child.name.clone(),
+                url: child.url.clone(),
+                file_type: String::from(child.type_str),
+            })
+            .collect()
+    }
 
-        result
+    // The introduction's HTML, for directories that have one
+    fn get_content(&self, context: &crate::AppContext<'_>) -> Result<Option<String>> {
+        let path = match intro(context, &self.path) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let contents = read_file(&path)?;
+        let (meta, body) =
+            front_matter::split(&contents).map_err(|message| Error::FrontMatter {
+                path: path.clone(),
+                message,
+            })?;
+        let (content, _) = render_content(context, &path, &meta, body);
+        Ok(Some(content))
     }
 }
 
+// A directory's introduction, shown on its page above what's in it. An
+// ignored `index.md` leaves the way for a `README.md`.
+pub fn intro(context: &crate::AppContext<'_>, dir: &Path) -> Option<PathBuf> {
+    INTRO_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file() && !context.is_ignored(path))
+}
+
+// Whether `path` is its directory's introduction, which has no page of its
+// own
+pub fn is_intro(context: &crate::AppContext<'_>, path: &Path) -> bool {
+    path.parent()
+        .and_then(|dir| intro(context, dir))
+        .is_some_and(|intro| intro == path)
+}
+
+pub fn dir_url(context: &crate::AppContext<'_>, path: &Path) -> String {
+    let relative = path
+        .my_relative_from(&context.root_notes)
+        .expect("Problem parsing relative url");
+    // The root of `notes/` is an empty path
+    let relative = match relative.to_string_lossy().as_ref() {
+        "" | "." => String::new(),
+        relative => format!("{}/", relative),
+    };
+    format!("{}{}", context.base_url, relative)
+}
+
 impl FileType for Dir {
     fn get_url(&self, context: &crate::AppContext<'_>) -> String {
+        dir_url(context, &self.path)
+    }
+
+    fn get_dest(&self, context: &crate::AppContext<'_>) -> PathBuf {
         let relative = self
             .path
             .my_relative_from(&context.root_notes)
             .expect("Problem parsing relative url");
-        let relative = if relative.to_str().unwrap() == "." {
-            String::new()
-        } else {
-            format!("{}/", relative.to_str().unwrap())
+        context.root_dest.join(relative).join("index.html")
+    }
+
+    // The index only shows the listing and the introduction, so that's all
+    // that needs comparing
+    fn fingerprint(&self, context: &crate::AppContext<'_>) -> Result<String> {
+        let children = self.get_children(context);
+        let intro = match intro(context, &self.path) {
+            Some(path) => {
+                let hash = hash_file(&path).map_err(Error::io("Could not read", &path))?;
+                Some((path, hash))
+            }
+            None => None,
         };
-        format!("{}{}", context.base_url, relative)
+        Ok(hash_str(
+            &serde_json::to_string(&(children, intro)).expect("Could not serialize children"),
+        ))
+    }
+
+    fn site_fingerprint(&self, context: &crate::AppContext<'_>) -> String {
+        intro(context, &self.path)
+            .map_or_else(String::new, |path| links_fingerprint(context, &path))
     }
 
-    fn convert(&self, context: &crate::AppContext<'_>) {
+    fn convert(&self, context: &crate::AppContext<'_>) -> Result<()> {
         let relative = self
             .path
             .my_relative_from(&context.root_notes)
             .expect("Problem parsing relative url");
-        let new_dir = context.root_dest.join(&relative);
-        let new_dir_index = new_dir.join("index.html");
-        if !metadata(&new_dir).is_ok() {
-            fs::create_dir(&new_dir)
-                .ok()
-                .expect("Cannot create destination subdir");
+        let new_dir = context.root_dest.join(relative);
+        let new_dir_index = self.get_dest(context);
+        if metadata(&new_dir).is_err() {
+            fs::create_dir(&new_dir).map_err(Error::io("Could not create", &new_dir))?;
         }
         let children = self.get_children(context);
+        let content = self.get_content(context)?;
         let name = match relative.file_name() {
-            Some(_) => String::from(relative.file_name().unwrap().to_str().unwrap()),
+            Some(_) => String::from(relative.file_name().unwrap().to_string_lossy()),
             None => String::from("root"),
         };
-        let parents = create_parent_links(&context.base_url, &relative, true);
+        let parents = create_parent_links(&context.base_url, relative, true);
         let dir_model = DirModel {
-            name: name,
-            parents: parents,
-            children: children,
-            base_url: context.base_url.clone(),
+            name,
+            parents,
+            content,
+            children,
+            common: Common::new(context),
         };
-        match context.handlebars.render(TYPE_STR, &dir_model) {
-            Ok(rendered) => {
-                // Create File
-                let mut file = File::create(&new_dir_index)
-                    .ok()
-                    .expect("Could not create dir index.html file");
-                //fs::chmod(&new_dir_index, USER_FILE).ok().expect("Couldn't chmod new file");
-                file.write_all(rendered.as_bytes())
-                    .ok()
-                    .expect("Could not write html to file");
-            }
-            Err(why) => panic!("Error rendering markdown: {:?}", why),
-        }
+        let rendered = context
+            .handlebars
+            .render(TYPE_STR, &dir_model)
+            .map_err(|why| Error::Render {
+                path: self.path.clone(),
+                source: Box::new(why),
+            })?;
+        // Create File
+        let mut file =
+            File::create(&new_dir_index).map_err(Error::io("Could not create", &new_dir_index))?;
+        //fs::chmod(&new_dir_index, USER_FILE).ok().expect("Couldn't chmod new file");
+        file.write_all(rendered.as_bytes())
+            .map_err(Error::io("Could not write", &new_dir_index))
     }
 
     fn get_type_str(&self) -> &'static str {
@@ -178,11 +222,14 @@ struct Child {
 }
 
 #[derive(Serialize)]
-struct DirModel {
+struct DirModel<'c> {
     name: String,
     parents: Vec<Link>,
+    // The introduction's HTML, if the directory has one
+    content: Option<String>,
     children: Vec<Child>,
-    base_url: String,
+    #[serde(flatten)]
+    common: Common<'c>,
 }
 
 // impl ToJson for DirModel {
@@ -1,79 +1,80 @@
 use std::fs::{metadata, File};
-use std::io::{Read, Write};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use pulldown_cmark::html;
-use pulldown_cmark::Parser;
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
 
 use serde::Serialize;
 
-use crate::file_type::{create_parent_links, read_file, FileType, Link};
-use crate::util::RelativeFrom;
+use crate::error::{Error, Result};
+use crate::file_type::{
+    create_parent_links, dir_url, is_intro, read_file, read_layout, read_partial,
+    register_template, Common, FileType, Link,
+};
+use crate::front_matter::{self, Meta};
+use crate::headings::{render_headings, TocEntry};
+use crate::highlight::Highlighter;
+use crate::index::{self, NoteLink};
+use crate::links::{self, render_relative_links, render_wiki_links};
+use crate::math::{self, Formula};
+use crate::tags::{tag_links, TagLink};
+use crate::util::{hash_file, hash_str, RelativeFrom};
 
-static TYPE_STR: &'static str = "markdown";
+static TYPE_STR: &str = "markdown";
 
 pub struct MarkdownFactory;
 
 impl crate::file_type::FileTypeFactory for MarkdownFactory {
-    fn try_create(&self, path: &Path) -> Option<Box<dyn FileType>> {
-        let name = path.file_name().unwrap().to_str().unwrap();
-        let path_metadata = metadata(&path).ok().expect("Could not fetch file metadata");
-        let is_valid = path_metadata.is_file()
-            && (name.ends_with(".md") || name.ends_with(".markdown") || name.ends_with(".mkd"));
-        if is_valid {
+    fn try_create(&self, path: &Path) -> Result<Option<Box<dyn FileType>>> {
+        let path_metadata =
+            metadata(path).map_err(Error::io("Could not read metadata of", path))?;
+        if path_metadata.is_file() && is_markdown_path(path) {
             let result = Markdown {
                 path: PathBuf::from(path),
                 type_str: TYPE_STR,
             };
-            Some(Box::new(result))
+            Ok(Some(Box::new(result)))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn initialize(&self, app_context: &mut crate::AppContext<'_>) -> Result<(), &'static str> {
-        let header_hbs_path = app_context.root_source.join("partials/header.hbs");
-        if !metadata(&header_hbs_path).is_ok() {
-            return Err("Missing partials/header.hbs");
-        }
-
-        let footer_hbs_path = app_context.root_source.join("partials/footer.hbs");
-        if !metadata(&footer_hbs_path).is_ok() {
-            return Err("Missing partials/footer.hbs");
-        }
-
-        let note_hbs_path = app_context.root_source.join("layouts/note.hbs");
-        if !metadata(&note_hbs_path).is_ok() {
-            return Err("Missing /layouts/note.hbs");
-        }
-
-        let header_hbs_contents = read_file(&header_hbs_path)?;
-        let footer_hbs_contents = read_file(&footer_hbs_path)?;
-        let note_hbs_contents = read_file(&note_hbs_path)?;
-        app_context
-            .handlebars
-            .register_template_string(
-                TYPE_STR,
-                format!(
-                    "{}\n{}\n{}",
-                    header_hbs_contents, note_hbs_contents, footer_hbs_contents
-                ),
-            )
-            .ok()
-            .expect("Error registering header|note|footer template");
-
-        Ok(())
+    fn initialize(&self, app_context: &mut crate::AppContext<'_>) -> Result<()> {
+        let header_hbs_contents = read_partial(app_context, "header.hbs")?;
+        let footer_hbs_contents = read_partial(app_context, "footer.hbs")?;
+        let note_hbs_contents = read_layout(app_context, "note.hbs")?;
+        register_template(
+            app_context,
+            TYPE_STR,
+            format!(
+                "{}\n{}\n{}",
+                header_hbs_contents, note_hbs_contents, footer_hbs_contents
+            ),
+        )
     }
 }
 
+pub fn is_markdown_path(path: &Path) -> bool {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return false,
+    };
+    name.ends_with(".md") || name.ends_with(".markdown") || name.ends_with(".mkd")
+}
+
 pub struct Markdown {
     path: PathBuf,
     type_str: &'static str,
 }
 
 impl FileType for Markdown {
+    // Directory introductions are shown on the directory's page
     fn get_url(&self, context: &crate::AppContext<'_>) -> String {
-        let file_name = self.path.file_stem().unwrap().to_str().unwrap();
+        if is_intro(context, &self.path) {
+            return dir_url(context, self.path.parent().unwrap());
+        }
+        let file_name = self.path.file_stem().unwrap().to_string_lossy();
         let relative = self
             .path
             .my_relative_from(&context.root_notes)
@@ -84,57 +85,82 @@ impl FileType for Markdown {
         {
             String::new()
         } else {
-            format!("{}/", relative.parent().unwrap().to_str().unwrap())
+            format!("{}/", relative.parent().unwrap().to_string_lossy())
         };
-        format!(
-            "{}{}{}",
-            context.base_url,
-            parent_relative,
-            format!("{}.html", file_name)
-        )
+        format!("{}{}{}.html", context.base_url, parent_relative, file_name)
     }
 
-    fn convert(&self, context: &crate::AppContext<'_>) {
+    fn get_dest(&self, context: &crate::AppContext<'_>) -> PathBuf {
         let relative = self
             .path
             .my_relative_from(&context.root_notes)
             .expect("Problem parsing relative url");
-        let file_name = relative.file_stem().unwrap().to_str().unwrap();
-        let dest_file = context
+        if is_intro(context, &self.path) {
+            return context
+                .root_dest
+                .join(relative.parent().unwrap())
+                .join("index.html");
+        }
+        let file_name = relative.file_stem().unwrap().to_string_lossy();
+        context
             .root_dest
-            .clone()
             .join(relative.parent().unwrap())
-            .join(format!("{}.html", file_name));
-        let mut source_contents = String::new();
-        File::open(&self.path)
-            .ok()
-            .unwrap()
-            .read_to_string(&mut source_contents)
-            .ok()
-            .expect("Could not read markdown file");
+            .join(format!("{}.html", file_name))
+    }
+
+    fn fingerprint(&self, _: &crate::AppContext<'_>) -> Result<String> {
+        hash_file(&self.path).map_err(Error::io("Could not read", &self.path))
+    }
+
+    // Where each link goes depends on which notes and assets exist, and the
+    // backlinks on which notes link here
+    fn site_fingerprint(&self, context: &crate::AppContext<'_>) -> String {
+        links_fingerprint(context, &self.path)
+    }
+
+    fn convert(&self, context: &crate::AppContext<'_>) -> Result<()> {
+        let relative = self
+            .path
+            .my_relative_from(&context.root_notes)
+            .expect("Problem parsing relative url");
+        let file_name = relative.file_stem().unwrap().to_string_lossy();
+        let dest_file = self.get_dest(context);
+        let source_contents = read_file(&self.path)?;
+        let (meta, body) =
+            front_matter::split(&source_contents).map_err(|message| Error::FrontMatter {
+                path: self.path.clone(),
+                message,
+            })?;
         // Create Model
-        let content = render_html(&source_contents);
-        let parents = create_parent_links(&context.base_url, &relative, false);
+        let (content, toc) = render_content(context, &self.path, &meta, body);
+        let parents = create_parent_links(&context.base_url, relative, false);
+        let tags = tag_links(context, &index::tags(&meta));
+        let backlinks = context.index.backlinks(&self.path);
 
         let model = MarkdownModel {
             name: String::from(file_name),
-            parents: parents,
-            content: format!("{}", content),
-            base_url: context.base_url.clone(),
+            parents,
+            content,
+            toc,
+            tags,
+            backlinks,
+            meta,
+            common: Common::new(context),
         };
-        match context.handlebars.render(TYPE_STR, &model) {
-            Ok(rendered) => {
-                // Create File
-                let mut file = File::create(&dest_file)
-                    .ok()
-                    .expect("Could not create markdown html file");
-                //fs::chmod(&dest_file, USER_FILE).ok().expect("Couldn't chmod new file");
-                file.write_all(rendered.as_bytes())
-                    .ok()
-                    .expect("Could not write html to file");
-            }
-            Err(why) => panic!("Error rendering markdown: {:?}", why),
-        }
+        let rendered =
+            context
+                .handlebars
+                .render(TYPE_STR, &model)
+                .map_err(|why| Error::Render {
+                    path: self.path.clone(),
+                    source: Box::new(why),
+                })?;
+        // Create File
+        let mut file =
+            File::create(&dest_file).map_err(Error::io("Could not create", &dest_file))?;
+        //fs::chmod(&dest_file, USER_FILE).ok().expect("Couldn't chmod new file");
+        file.write_all(rendered.as_bytes())
+            .map_err(Error::io("Could not write", &dest_file))
     }
 
     fn get_type_str(&self) -> &'static str {
@@ -143,11 +169,16 @@ impl FileType for Markdown {
 }
 
 #[derive(Serialize)]
-struct MarkdownModel {
+struct MarkdownModel<'c> {
     name: String,
     parents: Vec<Link>,
     content: String,
-    base_url: String,
+    toc: Vec<TocEntry>,
+    tags: Vec<TagLink>,
+    backlinks: Vec<NoteLink>,
+    meta: Meta,
+    #[serde(flatten)]
+    common: Common<'c>,
 }
 
 // impl ToJson for MarkdownModel {
@@ -156,9 +187,151 @@ struct MarkdownModel {
 //     }
 // }
 
-fn render_html(text: &str) -> String {
+// Hash of where the links and tags of the note at `path` point and of the
+// notes linking to it
+pub fn links_fingerprint(context: &crate::AppContext<'_>, path: &Path) -> String {
+    let note = match context.index.get(path) {
+        Some(note) => note,
+        None => return String::new(),
+    };
+    let urls: Vec<Option<String>> = note
+        .links
+        .iter()
+        .map(|target| context.index.resolve_url(path, target))
+        .collect();
+    let hrefs: Vec<Option<String>> = note
+        .hrefs
+        .iter()
+        .map(|href| links::source_url(context, path, href))
+        .collect();
+    let tags = tag_links(context, &note.tags);
+    let backlinks = context.index.backlinks(path);
+    hash_str(
+        &serde_json::to_string(&(urls, hrefs, tags, backlinks)).expect("Could not serialize links"),
+    )
+}
+
+// The HTML and table of contents of the note at `path`, whose front matter
+// is `meta`
+pub fn render_content(
+    context: &crate::AppContext<'_>,
+    path: &Path,
+    meta: &Meta,
+    body: &str,
+) -> (String, Vec<TocEntry>) {
+    let options = context.config.markdown.options(meta);
+    let (text, formulas) = extract_formulas(context, meta, body, options);
+    render_html(
+        &text,
+        options,
+        &formulas,
+        context.highlighter.as_ref(),
+        |target| context.index.resolve_url(path, target),
+        |href| links::source_url(context, path, href),
+    )
+}
+
+// `resolve` gives the url of a wiki link target, if there's a note for it,
+// and `resolve_href` the url of a relative link's source file. `text` has
+// placeholders for `formulas`. Also gives the note's table of contents.
+fn render_html<F, G>(
+    text: &str,
+    options: Options,
+    formulas: &[Formula],
+    highlighter: Option<&Highlighter>,
+    resolve: F,
+    resolve_href: G,
+) -> (String, Vec<TocEntry>)
+where
+    F: FnMut(&str) -> Option<String>,
+    G: FnMut(&str) -> Option<String>,
+{
     let mut s = String::with_capacity(text.len() * 3 / 2);
-    let p = Parser::new(&text);
-    html::push_html(&mut s, p);
+    let events = note_events(text, options, resolve, resolve_href);
+    let mut events = math::render_formulas(events, formulas);
+    if let Some(highlighter) = highlighter {
+        events = highlighter.render_code_blocks(events);
+    }
+    let (events, toc) = render_headings(events);
+    html::push_html(&mut s, events.into_iter());
+    (s, toc)
+}
+
+fn note_events<F, G>(text: &str, options: Options, resolve: F, resolve_href: G) -> Vec<Event<'_>>
+where
+    F: FnMut(&str) -> Option<String>,
+    G: FnMut(&str) -> Option<String>,
+{
+    let p = Parser::new_ext(text, options);
+    let events = render_relative_links(p.collect(), resolve_href);
+    render_wiki_links(events, resolve)
+}
+
+// The first paragraph of the note at `path`, with links rendered like on its
+// page but absolute, starting with `hostname`. Empty if it has none.
+pub fn render_summary(
+    context: &crate::AppContext<'_>,
+    path: &Path,
+    meta: &Meta,
+    body: &str,
+    hostname: &str,
+) -> String {
+    let options = context.config.markdown.options(meta);
+    let (text, formulas) = extract_formulas(context, meta, body, options);
+    // Wiki links are already HTML by the time the other links are seen
+    let events = note_events(
+        &text,
+        options,
+        |target| {
+            let url = context.index.resolve_url(path, target)?;
+            Some(absolute(hostname, CowStr::from(url)).to_string())
+        },
+        |href| links::source_url(context, path, href),
+    );
+    let events = math::render_formulas(events, &formulas);
+    let paragraph = events
+        .iter()
+        .position(|event| matches!(event, Event::Start(Tag::Paragraph)))
+        .and_then(|start| {
+            let end = events[start..]
+                .iter()
+                .position(|event| matches!(event, Event::End(Tag::Paragraph)))?;
+            Some(&events[start..=start + end])
+        });
+    let mut s = String::new();
+    if let Some(paragraph) = paragraph {
+        let events = paragraph.iter().cloned().map(|event| match event {
+            Event::Start(Tag::Link(kind, url, title)) => {
+                Event::Start(Tag::Link(kind, absolute(hostname, url), title))
+            }
+            Event::Start(Tag::Image(kind, url, title)) => {
+                Event::Start(Tag::Image(kind, absolute(hostname, url), title))
+            }
+            event => event,
+        });
+        html::push_html(&mut s, events);
+    }
     s
 }
+
+fn extract_formulas(
+    context: &crate::AppContext<'_>,
+    meta: &Meta,
+    body: &str,
+    options: Options,
+) -> (String, Vec<Formula>) {
+    if context.config.markdown.math(meta) {
+        math::extract(body, options)
+    } else {
+        (String::from(body), Vec::new())
+    }
+}
+
+// Puts `hostname` in front of urls that start at the root of it
+fn absolute<'u>(hostname: &str, url: CowStr<'u>) -> CowStr<'u> {
+    if url.starts_with('/') && !url.starts_with("//") {
+        CowStr::from(format!("{}{}", hostname, url))
+    } else {
+        url
+    }
+}
@@ -1,84 +1,156 @@
-use std::fs::File;
-use std::io::Read;
+use std::fs;
 use std::path::{Path, PathBuf};
 
 use serde::Serialize;
 
+use crate::config::Site;
+use crate::error::{Error, Result};
+use crate::Config;
+
 mod dir;
 mod markdown;
 mod unknown;
 
-pub trait FileType {
+pub(crate) use self::dir::{dir_url, is_intro};
+pub use self::markdown::is_markdown_path;
+pub(crate) use self::markdown::render_summary;
+
+pub trait FileType: Send + Sync {
     fn get_url(&self, context: &crate::AppContext<'_>) -> String;
-    fn convert(&self, context: &crate::AppContext<'_>);
+    fn get_dest(&self, context: &crate::AppContext<'_>) -> PathBuf;
+    // Hash of everything besides the template that goes into the output.
+    // Unchanged fingerprints let incremental builds skip `convert`.
+    fn fingerprint(&self, context: &crate::AppContext<'_>) -> Result<String>;
+    // Hash of what the output takes from the rest of the site, e.g. where
+    // its links point. Unlike `fingerprint` it's worked out on every build.
+    fn site_fingerprint(&self, _context: &crate::AppContext<'_>) -> String {
+        String::new()
+    }
+    fn convert(&self, context: &crate::AppContext<'_>) -> Result<()>;
     fn get_type_str(&self) -> &'static str;
 }
 
-trait FileTypeFactory {
-    fn try_create(&self, path: &Path) -> Option<Box<dyn FileType>>;
-    fn initialize(&self, app_context: &mut crate::AppContext<'_>) -> Result<(), &'static str>;
+pub trait FileTypeFactory: Send + Sync {
+    fn try_create(&self, path: &Path) -> Result<Option<Box<dyn FileType>>>;
+    fn initialize(&self, app_context: &mut crate::AppContext<'_>) -> Result<()>;
 }
 
-pub struct FileTypeManager {
+pub(crate) struct FileTypeManager {
     factories: Vec<Box<dyn FileTypeFactory>>,
     unknown_factory: unknown::UnknownFactory,
 }
 
 impl FileTypeManager {
-    pub fn new() -> FileTypeManager {
+    // Built in types come after `factories`, and unknown files after those
+    pub fn with_factories(mut factories: Vec<Box<dyn FileTypeFactory>>) -> FileTypeManager {
+        factories.push(Box::new(markdown::MarkdownFactory));
+        factories.push(Box::new(dir::DirFactory));
         FileTypeManager {
-            factories: vec![
-                Box::new(markdown::MarkdownFactory),
-                Box::new(dir::DirFactory),
-            ],
+            factories,
             unknown_factory: unknown::UnknownFactory,
         }
     }
 
-    pub fn initialize_app_context(
-        &self,
-        app_context: &mut crate::AppContext<'_>,
-    ) -> Result<(), &'static str> {
+    pub fn initialize_app_context(&self, app_context: &mut crate::AppContext<'_>) -> Result<()> {
         for factory in self.factories.iter() {
             factory.initialize(app_context)?;
         }
         Ok(())
     }
 
-    pub fn create_file_type<P: AsRef<Path>>(&self, path: P) -> Box<dyn FileType> {
+    pub fn create_file_type<P: AsRef<Path>>(&self, path: P) -> Result<Box<dyn FileType>> {
         for factory in self.factories.iter() {
-            let result_maybe = factory.try_create(path.as_ref());
-            if result_maybe.is_some() {
-                return result_maybe.unwrap();
+            if let Some(result) = factory.try_create(path.as_ref())? {
+                return Ok(result);
             }
         }
-        self.unknown_factory.try_create(path.as_ref()).unwrap()
+        Ok(self.unknown_factory.try_create(path.as_ref())?.unwrap())
+    }
+}
+
+// Registers `header|layout|footer` as one template and remembers its hash so
+// pages rendered with an older version of it can be told apart.
+pub fn register_template(
+    app_context: &mut crate::AppContext<'_>,
+    name: &'static str,
+    contents: String,
+) -> Result<()> {
+    app_context
+        .template_hashes
+        .insert(name, crate::util::hash_str(&contents));
+    app_context
+        .handlebars
+        .register_template_string(name, contents)
+        .map_err(|why| Error::Template {
+            name: String::from(name),
+            source: Box::new(why),
+        })
+}
+
+// Reads a layout every site must have, e.g. `note.hbs`, from the configured
+// layouts directory
+pub fn read_layout(app_context: &crate::AppContext<'_>, name: &str) -> Result<String> {
+    let dir = &app_context.config.templates.layouts;
+    read_template(app_context.root_source.join(dir).join(name))
+}
+
+// Reads a partial every site must have, e.g. `header.hbs`
+pub fn read_partial(app_context: &crate::AppContext<'_>, name: &str) -> Result<String> {
+    let dir = &app_context.config.templates.partials;
+    read_template(app_context.root_source.join(dir).join(name))
+}
+
+fn read_template(path: PathBuf) -> Result<String> {
+    if !path.is_file() {
+        return Err(Error::InvalidSource {
+            path,
+            reason: "Missing required template",
+        });
+    }
+    read_file(&path)
+}
+
+// Fields every page model has, flattened into it
+#[derive(Serialize)]
+pub(crate) struct Common<'c> {
+    base_url: &'c str,
+    config: &'c Config,
+    site: &'c Site,
+}
+
+impl<'c> Common<'c> {
+    pub fn new(context: &'c crate::AppContext<'_>) -> Common<'c> {
+        Common {
+            base_url: &context.base_url,
+            config: &context.config,
+            site: &context.site,
+        }
     }
 }
 
 #[derive(Serialize)]
-struct Link {
-    name: String,
-    url: String,
+pub(crate) struct Link {
+    pub name: String,
+    pub url: String,
 }
 
-fn create_parent_links(base_url: &str, path: &Path, is_dir: bool) -> Vec<Link> {
+pub(crate) fn create_parent_links(base_url: &str, path: &Path, is_dir: bool) -> Vec<Link> {
     if is_dir && path.file_name().is_none() {
         Vec::new()
     } else {
         let mut result: Vec<Link> = vec![Link {
             name: String::from("root"),
-            url: format!("{}", base_url),
+            url: base_url.to_string(),
         }];
-        let mut temp = PathBuf::from(path.clone().parent().unwrap());
+        let mut temp = PathBuf::from(path.parent().unwrap());
         while temp.file_name().is_some() {
-            let file_name = String::from(temp.file_name().unwrap().to_str().unwrap());
-            let url = format!("{}{}", &base_url, temp.to_str().unwrap());
+            let file_name = String::from(temp.file_name().unwrap().to_string_lossy());
+            let url = format!("{}{}", &base_url, temp.to_string_lossy());
             result.insert(
                 1,
                 Link {
                     name: file_name,
-                    url: url,
+                    url,
                 },
             );
             temp.pop();
@@ -87,16 +159,7 @@ fn create_parent_links(base_url: &str, path: &Path, is_dir: bool) -> Vec<Link> {
     }
 }
 
-pub fn read_file<P: AsRef<Path>>(path: P) -> Result<String, &'static str> {
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<String> {
     let path: &Path = path.as_ref();
-    let mut file = match File::open(&path) {
-        Ok(ok_file) => ok_file,
-        Err(_) => return Err("Could not open path."),
-    };
-    let mut contents = String::new();
-    match file.read_to_string(&mut contents) {
-        Err(_) => return Err("Could not read file."),
-        _ => (),
-    }
-    Ok(contents)
+    fs::read_to_string(path).map_err(Error::io("Could not read", path))
 }
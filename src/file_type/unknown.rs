@@ -1,23 +1,24 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use crate::util::RelativeFrom;
+use crate::util::{hash_file, RelativeFrom};
 
+use crate::error::{Error, Result};
 use crate::file_type::FileType;
 
-static TYPE_STR: &'static str = "unknown";
+static TYPE_STR: &str = "unknown";
 
 pub struct UnknownFactory;
 
 impl crate::file_type::FileTypeFactory for UnknownFactory {
-    fn try_create(&self, path: &Path) -> Option<Box<dyn FileType>> {
-        Some(Box::new(Unknown {
+    fn try_create(&self, path: &Path) -> Result<Option<Box<dyn FileType>>> {
+        Ok(Some(Box::new(Unknown {
             path: PathBuf::from(path),
             type_str: TYPE_STR,
-        }))
+        })))
     }
 
-    fn initialize(&self, _: &mut crate::AppContext<'_>) -> Result<(), &'static str> {
+    fn initialize(&self, _: &mut crate::AppContext<'_>) -> Result<()> {
         Ok(())
     }
 }
@@ -37,25 +38,32 @@ impl crate::file_type::FileType for Unknown {
         let parent_relative = if relative.parent().unwrap() == Path::new("") {
             String::from("")
         } else {
-            format!("{}/", relative.parent().unwrap().to_str().unwrap())
+            format!("{}/", relative.parent().unwrap().to_string_lossy())
         };
         format!(
             "{}{}{}",
             context.base_url,
             parent_relative,
-            file_name.to_str().unwrap()
+            file_name.to_string_lossy()
         )
     }
 
-    fn convert(&self, context: &crate::AppContext<'_>) {
+    fn get_dest(&self, context: &crate::AppContext<'_>) -> PathBuf {
         let relative = self
             .path
             .my_relative_from(&context.root_notes)
             .expect("Problem parsing relative url");
-        let destination = context.root_dest.join(&relative);
-        fs::copy(&self.path, &destination)
-            .ok()
-            .expect("Problem copying unknown file");
+        context.root_dest.join(relative)
+    }
+
+    fn fingerprint(&self, _: &crate::AppContext<'_>) -> Result<String> {
+        hash_file(&self.path).map_err(Error::io("Could not read", &self.path))
+    }
+
+    fn convert(&self, context: &crate::AppContext<'_>) -> Result<()> {
+        fs::copy(&self.path, self.get_dest(context))
+            .map_err(Error::io("Could not copy", &self.path))?;
+        Ok(())
     }
 
     fn get_type_str(&self) -> &'static str {
@@ -0,0 +1,185 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde_json::{Map, Value};
+
+pub type Meta = Map<String, Value>;
+
+// Splits a leading front matter block off of a markdown document. A block
+// fenced with `---` is parsed as YAML, one fenced with `+++` as TOML.
+// Documents without a block yield an empty map and the whole text as body,
+// and so do those where `---` blocks hold something other than a mapping,
+// since a leading `---` can also be a thematic break.
+pub fn split(text: &str) -> Result<(Meta, &str), String> {
+    let text = text.trim_start_matches('\u{feff}');
+    let (fence, is_yaml) = if starts_with_fence(text, "---") {
+        ("---", true)
+    } else if starts_with_fence(text, "+++") {
+        ("+++", false)
+    } else {
+        return Ok((Meta::new(), text));
+    };
+
+    let block_start = text.find('\n').map_or(text.len(), |i| i + 1);
+    let mut offset = block_start;
+    for line in text[block_start..].split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == fence || (is_yaml && trimmed == "...") {
+            let block = &text[block_start..offset];
+            let body = &text[offset + line.len()..];
+            let meta = if is_yaml {
+                match parse_yaml(block)? {
+                    Some(meta) => meta,
+                    None => return Ok((Meta::new(), text)),
+                }
+            } else {
+                parse_toml(block)?
+            };
+            return Ok((meta, body));
+        }
+        offset += line.len();
+    }
+
+    Err(format!(
+        "Unterminated front matter, expected closing `{}`",
+        fence
+    ))
+}
+
+fn starts_with_fence(text: &str, fence: &str) -> bool {
+    let first_line = text.lines().next().unwrap_or("");
+    first_line.trim_end() == fence
+}
+
+// `None` when the block is valid YAML but not a mapping
+fn parse_yaml(block: &str) -> Result<Option<Meta>, String> {
+    if block.trim().is_empty() {
+        return Ok(Some(Meta::new()));
+    }
+    match serde_yaml::from_str::<Value>(block) {
+        Ok(Value::Object(map)) => Ok(Some(map)),
+        Ok(Value::Null) => Ok(Some(Meta::new())),
+        Ok(_) => Ok(None),
+        Err(why) => Err(format!("Invalid YAML front matter: {}", why)),
+    }
+}
+
+fn parse_toml(block: &str) -> Result<Meta, String> {
+    match block.parse::<toml::Value>() {
+        Ok(toml::Value::Table(table)) => Ok(table
+            .into_iter()
+            .map(|(key, value)| (key, toml_to_json(value)))
+            .collect()),
+        Ok(_) => Err(String::from("TOML front matter must be a table")),
+        Err(why) => Err(format!("Invalid TOML front matter: {}", why)),
+    }
+}
+
+// toml's datetime type doesn't map cleanly onto json, so convert by hand and
+// keep datetimes as the string the author wrote.
+pub fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+// `date` from front matter, either RFC 3339, a date and time, or only a date.
+// Times without an offset are taken to be UTC.
+pub fn date(meta: &Meta) -> Option<DateTime<Utc>> {
+    let date = meta.get("date")?.as_str()?.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"].iter() {
+        if let Ok(date) = NaiveDateTime::parse_from_str(date, format) {
+            return Some(Utc.from_utc_datetime(&date));
+        }
+    }
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml() {
+        let (meta, body) = split("---\ntitle: Bread\ntags: [food]\n---\n# Bread\n").unwrap();
+        assert_eq!(meta["title"], "Bread");
+        assert_eq!(meta["tags"][0], "food");
+        assert_eq!(body, "# Bread\n");
+    }
+
+    #[test]
+    fn toml() {
+        let (meta, body) = split("+++\ntitle = \"Bread\"\ndate = 2024-05-01\n+++\nText").unwrap();
+        assert_eq!(meta["title"], "Bread");
+        assert_eq!(meta["date"], "2024-05-01");
+        assert_eq!(body, "Text");
+    }
+
+    #[test]
+    fn yaml_ended_with_dots() {
+        let (meta, body) = split("---\ntitle: Bread\n...\nText").unwrap();
+        assert_eq!(meta["title"], "Bread");
+        assert_eq!(body, "Text");
+    }
+
+    #[test]
+    fn dots_dont_end_toml() {
+        assert!(split("+++\ntitle = \"Bread\"\n...\nText").is_err());
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let (meta, body) = split("\u{feff}---\ntitle: Bread\n---\nText").unwrap();
+        assert_eq!(meta["title"], "Bread");
+        assert_eq!(body, "Text");
+    }
+
+    #[test]
+    fn unterminated() {
+        let why = split("---\ntitle: Bread\n\nText\n").unwrap_err();
+        assert!(why.contains("Unterminated"), "{}", why);
+    }
+
+    #[test]
+    fn no_front_matter() {
+        let (meta, body) = split("# Bread\n---\n").unwrap();
+        assert!(meta.is_empty());
+        assert_eq!(body, "# Bread\n---\n");
+    }
+
+    #[test]
+    fn thematic_breaks() {
+        let text = "---\nJust a rule above\n\n---\n";
+        let (meta, body) = split(text).unwrap();
+        assert!(meta.is_empty());
+        assert_eq!(body, text);
+        let text = "---\n- a list\n- of items\n---\nText";
+        assert_eq!(split(text).unwrap().1, text);
+    }
+
+    #[test]
+    fn invalid_mapping() {
+        let why = split("---\ntitle: Bread\n  tags: [food\n---\nText").unwrap_err();
+        assert!(why.contains("Invalid YAML"), "{}", why);
+    }
+
+    #[test]
+    fn empty_block() {
+        let (meta, body) = split("---\n---\nText").unwrap();
+        assert!(meta.is_empty());
+        assert_eq!(body, "Text");
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{CowStr, Event, Tag};
+use serde::Serialize;
+
+use crate::util::slugify;
+
+// A heading in a note's table of contents, with the headings below it
+#[derive(Serialize)]
+pub struct TocEntry {
+    pub title: String,
+    pub id: String,
+    pub level: u32,
+    pub children: Vec<TocEntry>,
+}
+
+// Gives every heading an `id` made from its text, numbering repeats like
+// `setup`, `setup-1`, and lists them as a table of contents
+pub fn render_headings(events: Vec<Event<'_>>) -> (Vec<Event<'_>>, Vec<TocEntry>) {
+    let mut result = Vec::with_capacity(events.len());
+    let mut headings: Vec<TocEntry> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let level = match event {
+            Event::Start(Tag::Heading(level)) => level,
+            event => {
+                result.push(event);
+                continue;
+            }
+        };
+        let mut title = String::new();
+        let mut inner = Vec::new();
+        for event in events.by_ref() {
+            match event {
+                Event::End(Tag::Heading(_)) => break,
+                Event::Text(ref text) | Event::Code(ref text) => title.push_str(text),
+                _ => (),
+            }
+            inner.push(event);
+        }
+        // Formulas and other HTML in a heading leave it out of the title
+        let title = String::from(title.trim());
+        let id = unique_id(&mut seen, &title);
+        let mut html = format!("<h{} id=\"", level);
+        escape_html(&mut html, &id).unwrap();
+        html.push_str("\">");
+        result.push(Event::Html(CowStr::from(html)));
+        result.extend(inner);
+        result.push(Event::Html(CowStr::from(format!("</h{}>\n", level))));
+        headings.push(TocEntry {
+            title,
+            id,
+            level,
+            children: Vec::new(),
+        });
+    }
+    (result, nest(headings))
+}
+
+fn unique_id(seen: &mut HashSet<String>, title: &str) -> String {
+    let mut slug = slugify(title);
+    if slug.is_empty() {
+        slug = String::from("section");
+    }
+    let mut id = slug.clone();
+    let mut n = 0;
+    while seen.contains(&id) {
+        n += 1;
+        id = format!("{}-{}", slug, n);
+    }
+    seen.insert(id.clone());
+    id
+}
+
+// Puts each heading below the closest one before it with a lower level
+fn nest(headings: Vec<TocEntry>) -> Vec<TocEntry> {
+    let mut roots: Vec<TocEntry> = Vec::new();
+    // Headings that later ones may still go below, outermost first
+    let mut open: Vec<TocEntry> = Vec::new();
+    for heading in headings {
+        while open.last().is_some_and(|last| last.level >= heading.level) {
+            close(&mut open, &mut roots);
+        }
+        open.push(heading);
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut roots);
+    }
+    roots
+}
+
+fn close(open: &mut Vec<TocEntry>, roots: &mut Vec<TocEntry>) {
+    let heading = open.pop().unwrap();
+    match open.last_mut() {
+        Some(parent) => parent.children.push(heading),
+        None => roots.push(heading),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::{html, Parser};
+
+    use super::*;
+
+    fn ids(titles: &[&str]) -> Vec<String> {
+        let mut seen = HashSet::new();
+        titles
+            .iter()
+            .map(|title| unique_id(&mut seen, title))
+            .collect()
+    }
+
+    fn heading(level: u32) -> TocEntry {
+        TocEntry {
+            title: format!("h{}", level),
+            id: String::new(),
+            level,
+            children: Vec::new(),
+        }
+    }
+
+    // Titles nested like `h1(h3 h2(h3)) h1`
+    fn outline(entries: &[TocEntry]) -> String {
+        entries
+            .iter()
+            .map(|entry| {
+                if entry.children.is_empty() {
+                    entry.title.clone()
+                } else {
+                    format!("{}({})", entry.title, outline(&entry.children))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn nested(levels: &[u32]) -> String {
+        outline(&nest(levels.iter().map(|&level| heading(level)).collect()))
+    }
+
+    #[test]
+    fn repeated_titles() {
+        assert_eq!(
+            ids(&["Setup", "Usage", "Setup", "setup"]),
+            ["setup", "usage", "setup-1", "setup-2"]
+        );
+    }
+
+    #[test]
+    fn titles_without_a_slug() {
+        assert_eq!(
+            ids(&["", "!!!", "Section"]),
+            ["section", "section-1", "section-2"]
+        );
+    }
+
+    #[test]
+    fn titles_like_numbered_repeats() {
+        assert_eq!(
+            ids(&["Setup 1", "Setup", "Setup"]),
+            ["setup-1", "setup", "setup-2"]
+        );
+        assert_eq!(
+            ids(&["Setup", "Setup", "Setup 1"]),
+            ["setup", "setup-1", "setup-1-1"]
+        );
+    }
+
+    #[test]
+    fn skipped_levels() {
+        assert_eq!(nested(&[1, 3, 2, 3, 1]), "h1(h3 h2(h3)) h1");
+        assert_eq!(nested(&[3, 1, 2]), "h3 h1(h2)");
+        assert_eq!(nested(&[2, 2, 4, 3]), "h2 h2(h4 h3)");
+        assert_eq!(nested(&[]), "");
+    }
+
+    #[test]
+    fn heading_ids_in_html() {
+        let (events, toc) = render_headings(Parser::new("# A `b`\n\n## A b\n\n# *C*").collect());
+        let mut rendered = String::new();
+        html::push_html(&mut rendered, events.into_iter());
+        assert_eq!(
+            rendered,
+            "<h1 id=\"a-b\">A <code>b</code></h1>\n<h2 id=\"a-b-1\">A b</h2>\n<h1 id=\"c\"><em>C</em></h1>\n"
+        );
+        let toc: Vec<(&str, &str, usize)> = toc
+            .iter()
+            .map(|entry| {
+                (
+                    entry.title.as_str(),
+                    entry.id.as_str(),
+                    entry.children.len(),
+                )
+            })
+            .collect();
+        assert_eq!(toc, [("A b", "a-b", 1), ("C", "c", 0)]);
+    }
+}
@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use serde::Serialize;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+use crate::config::{Config, CONFIG_FILE};
+use crate::error::{Error, Result};
+use crate::file_type::register_template;
+use crate::{AppContext, Generated};
+
+static TYPE_STR: &str = "highlight";
+
+// Where the theme's stylesheet goes in dest, unless the site has its own
+pub static CSS_FILE: &str = "assets/highlight.css";
+
+// Prefixed so theme classes can't clash with a site's own
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+// Highlights fenced code blocks at build time into spans with classes, which
+// the theme's stylesheet colors
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    // `None` when the config turns highlighting off. The theme is one of
+    // syntect's built in ones or a `.tmTheme` file relative to `source`.
+    pub fn new(source: &Path, config: &Config) -> Result<Option<Highlighter>> {
+        if !config.highlight.enabled {
+            return Ok(None);
+        }
+        let name = &config.highlight.theme;
+        let mut themes = ThemeSet::load_defaults();
+        let theme = if name.ends_with(".tmTheme") {
+            ThemeSet::get_theme(source.join(name)).map_err(|why| Error::Config {
+                path: source.join(CONFIG_FILE),
+                message: format!("Could not load highlight theme {}: {}", name, why),
+            })?
+        } else {
+            match themes.themes.remove(name) {
+                Some(theme) => theme,
+                None => {
+                    let known: Vec<&String> = themes.themes.keys().collect();
+                    return Err(Error::Config {
+                        path: source.join(CONFIG_FILE),
+                        message: format!(
+                            "Unknown highlight theme {:?}, expected a .tmTheme file or one of {:?}",
+                            name, known
+                        ),
+                    });
+                }
+            }
+        };
+        Ok(Some(Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+        }))
+    }
+
+    pub fn css(&self) -> String {
+        css_for_theme_with_class_style(&self.theme, CLASS_STYLE)
+            .expect("Could not write highlight theme")
+    }
+
+    // Replaces fenced code blocks in a language it knows with highlighted
+    // HTML. Any others are left as they are.
+    pub fn render_code_blocks<'e>(&self, events: Vec<Event<'e>>) -> Vec<Event<'e>> {
+        let mut result = Vec::with_capacity(events.len());
+        let mut events = events.into_iter();
+        while let Some(event) = events.next() {
+            let info = match event {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info))) => info.clone(),
+                event => {
+                    result.push(event);
+                    continue;
+                }
+            };
+            // The info string is the language and maybe more, e.g. `rust,ignore`
+            let language = info
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .next()
+                .unwrap_or("");
+            let syntax = match self.syntaxes.find_syntax_by_token(language) {
+                Some(syntax) if !language.is_empty() => syntax,
+                _ => {
+                    result.push(event);
+                    continue;
+                }
+            };
+            let mut code = String::new();
+            for event in events.by_ref() {
+                match event {
+                    Event::End(Tag::CodeBlock(_)) => break,
+                    Event::Text(text) => code.push_str(&text),
+                    _ => (),
+                }
+            }
+            let mut html = String::from("<pre class=\"hl-code\"><code class=\"language-");
+            escape_html(&mut html, language).unwrap();
+            html.push_str("\">");
+            // Syntaxes that trip over the code still get it, plain
+            match self.highlight(syntax, &code) {
+                Some(highlighted) => html.push_str(&highlighted),
+                None => escape_html(&mut html, &code).unwrap(),
+            }
+            html.push_str("</code></pre>\n");
+            result.push(Event::Html(CowStr::from(html)));
+        }
+        result
+    }
+
+    fn highlight(&self, syntax: &SyntaxReference, code: &str) -> Option<String> {
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntaxes, CLASS_STYLE);
+        for line in LinesWithEndings::from(code) {
+            generator
+                .parse_html_for_line_which_includes_newline(line)
+                .ok()?;
+        }
+        Some(generator.finalize())
+    }
+}
+
+pub fn initialize(app_context: &mut AppContext<'_>) -> Result<()> {
+    register_template(app_context, TYPE_STR, String::from("{{{css}}}"))
+}
+
+// The theme's stylesheet, for sites that highlight code and don't bring
+// their own
+pub fn pages(context: &AppContext<'_>) -> Vec<Generated> {
+    let highlighter = match context.highlighter {
+        Some(ref highlighter) => highlighter,
+        None => return Vec::new(),
+    };
+    if context.root_source.join(CSS_FILE).exists() {
+        return Vec::new();
+    }
+    vec![Generated {
+        key: String::from(CSS_FILE),
+        output: PathBuf::from(CSS_FILE),
+        type_str: TYPE_STR,
+        model: serde_json::to_value(CssModel {
+            css: highlighter.css(),
+        })
+        .expect("Could not serialize highlight theme"),
+    }]
+}
+
+#[derive(Serialize)]
+struct CssModel {
+    css: String,
+}
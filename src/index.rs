@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::file_type::{read_file, FileTypeManager};
+use crate::front_matter::{self, Meta};
+use crate::links;
+use crate::listing::{DirListing, LISTING_FILE};
+use crate::math;
+use crate::util::{slugify, RelativeFrom};
+use crate::AppContext;
+
+// Everything below `notes/` and how it links together, gathered before
+// anything is rendered so pages can show what's elsewhere on the site
+#[derive(Default)]
+pub struct Index {
+    nodes: HashMap<PathBuf, Node>,
+    // Sorted by url
+    pub notes: Vec<IndexedNote>,
+    // Position in `notes` of each note's source path
+    by_path: HashMap<PathBuf, usize>,
+    // Positions in `notes` by lowercased file stem and by lowercased key,
+    // for resolving wiki links
+    by_name: HashMap<String, Vec<usize>>,
+    by_key: HashMap<String, Vec<usize>>,
+    // Wiki links that lead to no note, by the path of the note they're in
+    unresolved: Vec<(PathBuf, String)>,
+}
+
+// A note, directory or any other file below `notes/`
+pub struct Node {
+    // File stem
+    pub name: String,
+    pub url: String,
+    pub type_str: &'static str,
+    // Where it's written, relative to dest
+    pub output: PathBuf,
+    // From front matter for notes, otherwise when the file was last modified
+    pub date: Option<DateTime<Utc>>,
+    // From front matter, for notes that have one
+    pub weight: Option<i64>,
+    // Paths of what a directory holds, in no particular order
+    pub children: Vec<PathBuf>,
+    // A directory's own listing settings, if it has any
+    pub listing: Option<DirListing>,
+}
+
+// What indexing one path found out
+pub struct Indexed {
+    path: PathBuf,
+    node: Node,
+    note: Option<IndexedNote>,
+}
+
+pub struct IndexedNote {
+    pub path: PathBuf,
+    // Path below `notes/` without the extension, e.g. `recipes/bread`
+    pub key: String,
+    pub name: String,
+    pub title: Option<String>,
+    pub url: String,
+    pub tags: Vec<String>,
+    // From front matter, or when the file was last modified
+    pub date: DateTime<Utc>,
+    pub weight: Option<i64>,
+    // Marked `draft` or `private` in front matter, so left out of feeds and
+    // the sitemap
+    pub private: bool,
+    // Wiki link targets, as written
+    pub links: Vec<String>,
+    // Relative link and image destinations, as written
+    pub hrefs: Vec<String>,
+    // Positions in `Index::notes` of the notes linking to this one
+    backlinks: Vec<usize>,
+}
+
+// A note as other pages list it
+#[derive(Serialize)]
+pub struct NoteLink {
+    pub name: String,
+    pub title: Option<String>,
+    pub url: String,
+}
+
+impl IndexedNote {
+    pub fn link(&self) -> NoteLink {
+        NoteLink {
+            name: self.name.clone(),
+            title: self.title.clone(),
+            url: self.url.clone(),
+        }
+    }
+}
+
+impl Index {
+    // Puts together what `index_path` found for every path, parents before
+    // their children
+    pub fn build(indexed: Vec<Indexed>) -> Index {
+        let mut nodes: HashMap<PathBuf, Node> = HashMap::with_capacity(indexed.len());
+        let mut notes: Vec<IndexedNote> = Vec::new();
+        for Indexed { path, node, note } in indexed {
+            if let Some(parent) = path.parent().and_then(|parent| nodes.get_mut(parent)) {
+                parent.children.push(path.clone());
+            }
+            nodes.insert(path, node);
+            notes.extend(note);
+        }
+        notes.sort_by(|a, b| a.url.cmp(&b.url));
+        let mut by_path = HashMap::with_capacity(notes.len());
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut by_key: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, note) in notes.iter().enumerate() {
+            by_path.insert(note.path.clone(), i);
+            by_name.entry(note.name.to_lowercase()).or_default().push(i);
+            by_key.entry(note.key.to_lowercase()).or_default().push(i);
+        }
+        let mut index = Index {
+            nodes,
+            notes,
+            by_path,
+            by_name,
+            by_key,
+            unresolved: Vec::new(),
+        };
+        index.find_backlinks();
+        index
+    }
+
+    // What's in the directory at `path`
+    pub fn children<'i>(
+        &'i self,
+        path: &Path,
+    ) -> impl Iterator<Item = (&'i PathBuf, &'i Node)> + 'i {
+        self.nodes
+            .get(path)
+            .into_iter()
+            .flat_map(|node| node.children.iter())
+            .filter_map(move |child| Some((child, self.nodes.get(child)?)))
+    }
+
+    pub fn node(&self, path: &Path) -> Option<&Node> {
+        self.nodes.get(path)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (&PathBuf, &Node)> {
+        self.nodes.iter()
+    }
+
+    fn find_backlinks(&mut self) {
+        let mut backlinks: Vec<Vec<usize>> = vec![Vec::new(); self.notes.len()];
+        let mut unresolved = Vec::new();
+        for (from, note) in self.notes.iter().enumerate() {
+            let mut linked = Vec::new();
+            // Only a `#heading` is a link within the note
+            for target in note.links.iter().filter(|target| !target.starts_with('#')) {
+                match self.resolve_position(&note.path, target) {
+                    Some(to) => linked.push(to),
+                    None => unresolved.push((note.path.clone(), target.clone())),
+                }
+            }
+            // Relative links to other notes count too
+            linked.extend(
+                note.hrefs
+                    .iter()
+                    .filter_map(|href| links::href_path(&note.path, href))
+                    .filter_map(|path| self.by_path.get(&path).copied()),
+            );
+            for to in linked {
+                if to != from && !backlinks[to].contains(&from) {
+                    backlinks[to].push(from);
+                }
+            }
+        }
+        for (note, mut backlinks) in self.notes.iter_mut().zip(backlinks) {
+            backlinks.sort_unstable();
+            note.backlinks = backlinks;
+        }
+        self.unresolved = unresolved;
+    }
+
+    // Every wiki link that leads to no note, along with the note it's in
+    pub fn unresolved(&self) -> impl Iterator<Item = (&PathBuf, &String)> {
+        self.unresolved.iter().map(|(path, target)| (path, target))
+    }
+
+    // Notes linking to the note at `path`, in url order
+    pub fn backlinks(&self, path: &Path) -> Vec<NoteLink> {
+        match self.get(path) {
+            Some(note) => note
+                .backlinks
+                .iter()
+                .map(|&i| self.notes[i].link())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&IndexedNote> {
+        self.by_path.get(path).map(|&i| &self.notes[i])
+    }
+
+    // Finds the note a wiki link in the note at `from` points to. Targets
+    // with a slash are paths below `notes/`, others are file stems, and a
+    // note next to `from` wins when several share a stem. Case is ignored,
+    // as is a markdown extension.
+    pub fn resolve(&self, from: &Path, target: &str) -> Option<&IndexedNote> {
+        self.resolve_position(from, target).map(|i| &self.notes[i])
+    }
+
+    fn resolve_position(&self, from: &Path, target: &str) -> Option<usize> {
+        let target = match target.find('#') {
+            Some(hash) => &target[..hash],
+            None => target,
+        };
+        let mut wanted = target.trim().trim_start_matches('/').to_lowercase();
+        for extension in [".md", ".markdown", ".mkd"].iter() {
+            if wanted.ends_with(extension) {
+                wanted.truncate(wanted.len() - extension.len());
+                break;
+            }
+        }
+        let found = if wanted.contains('/') {
+            self.by_key.get(&wanted)?
+        } else {
+            self.by_name.get(&wanted)?
+        };
+        let from_dir = from.parent();
+        found
+            .iter()
+            .copied()
+            .find(|&i| self.notes[i].path.parent() == from_dir)
+            .or_else(|| found.first().copied())
+    }
+
+    // Url a wiki link target goes to. A `#heading` goes to the id that
+    // heading gets.
+    pub fn resolve_url(&self, from: &Path, target: &str) -> Option<String> {
+        let (target, fragment) = match target.find('#') {
+            Some(hash) => (
+                &target[..hash],
+                format!("#{}", slugify(&target[hash + 1..])),
+            ),
+            None => (target, String::new()),
+        };
+        if target.is_empty() {
+            return Some(fragment);
+        }
+        let note = self.resolve(from, target)?;
+        Some(format!("{}{}", note.url, fragment))
+    }
+}
+
+// Works out the url and type of `path`, and for notes reads what they say
+// about themselves. Paths that can't be read are left out, converting them
+// reports the problem.
+pub fn index_path(
+    context: &AppContext<'_>,
+    file_type_manager: &FileTypeManager,
+    path: &Path,
+) -> Option<Indexed> {
+    let file_type = file_type_manager.create_file_type(path).ok()?;
+    let url = file_type.get_url(context);
+    let dest = file_type.get_dest(context);
+    let mut node = Node {
+        name: String::from(path.file_stem()?.to_str()?),
+        url: url.clone(),
+        type_str: file_type.get_type_str(),
+        output: PathBuf::from(dest.my_relative_from(&context.root_dest)?),
+        date: modified(path),
+        weight: None,
+        children: Vec::new(),
+        listing: None,
+    };
+    // Custom file types may have taken over some markdown files
+    let note = if node.type_str == "markdown" {
+        let note = index_note(context, path, url)?;
+        node.date = Some(note.date);
+        node.weight = note.weight;
+        Some(note)
+    } else {
+        None
+    };
+    if node.type_str == "dir" {
+        node.listing = match DirListing::load(path) {
+            Some(Ok(listing)) => Some(listing),
+            Some(Err(why)) => {
+                let message = format!("Invalid {}: {}", LISTING_FILE, why);
+                context.warn(&path.join(LISTING_FILE), message);
+                None
+            }
+            None => None,
+        };
+    }
+    Some(Indexed {
+        path: PathBuf::from(path),
+        node,
+        note,
+    })
+}
+
+fn index_note(context: &AppContext<'_>, path: &Path, url: String) -> Option<IndexedNote> {
+    let contents = read_file(path).ok()?;
+    let (meta, body) = front_matter::split(&contents).ok()?;
+    let options = context.config.markdown.options(&meta);
+    // Warned about here rather than while converting, which unchanged notes
+    // skip
+    if context.config.markdown.math(&meta) {
+        let (_, formulas) = math::extract(body, options);
+        for problem in math::problems(&formulas) {
+            context.warn(path, problem);
+        }
+    }
+    let date = front_matter::date(&meta).or_else(|| modified(path))?;
+    let relative = path
+        .my_relative_from(&context.root_notes)?
+        .with_extension("");
+    let key = relative
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?
+        .join("/");
+    Some(IndexedNote {
+        path: PathBuf::from(path),
+        key,
+        name: String::from(path.file_stem()?.to_str()?),
+        title: meta.get("title").and_then(Value::as_str).map(String::from),
+        url,
+        tags: tags(&meta),
+        date,
+        weight: meta.get("weight").and_then(Value::as_i64),
+        private: ["draft", "private"]
+            .iter()
+            .any(|key| meta.get(*key) == Some(&Value::Bool(true))),
+        links: links::wiki_targets(body, options),
+        hrefs: links::relative_hrefs(body, options),
+        backlinks: Vec::new(),
+    })
+}
+
+fn modified(path: &Path) -> Option<DateTime<Utc>> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(DateTime::from(modified))
+}
+
+// `tags` from front matter, either a list or a comma separated string
+pub fn tags(meta: &Meta) -> Vec<String> {
+    let tags: Vec<String> = match meta.get("tags") {
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| match item {
+                Value::String(tag) => Some(tag.clone()),
+                Value::Number(number) => Some(number.to_string()),
+                _ => None,
+            })
+            .collect(),
+        Some(Value::String(tags)) => tags.split(',').map(String::from).collect(),
+        _ => Vec::new(),
+    };
+    let mut result: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !result.iter().any(|seen| seen == tag) {
+            result.push(String::from(tag));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::testing::TestSite;
+    use crate::Builder;
+
+    #[test]
+    fn backlinks_from_wiki_and_relative_links() {
+        let site = TestSite::new(
+            "backlinks",
+            &[
+                ("layouts/note.hbs", "{{#each backlinks}}{{name}} {{/each}}"),
+                ("notes/a.md", "[[a]] [itself](a.md)"),
+                ("notes/b.md", "[link](a.md)"),
+                ("notes/c.md", "[[a]] and [again](./a.md#Top)"),
+                ("notes/sub/d.md", "![](../a.md) [up](../a.md?x)"),
+                ("notes/e.md", "[elsewhere](https://example.com/a.md) [[b]]"),
+            ],
+        );
+        let build = || Builder::new(site.source(), site.dest()).build().unwrap();
+        build();
+        assert_eq!(site.output("a.html").trim(), "b c d");
+        assert_eq!(site.output("b.html").trim(), "e");
+
+        // A new link changes the page it leads to
+        site.write("notes/f.md", "[link](sub/../a.md)");
+        let report = build();
+        assert!(report.written.contains(&PathBuf::from("a.html")));
+        assert_eq!(site.output("a.html").trim(), "b c f d");
+    }
+
+    // Unchanged notes aren't converted again, but their problems still matter
+    #[test]
+    fn warnings_on_every_build() {
+        let site = TestSite::new(
+            "index-warnings",
+            &[
+                ("notes/a.md", "$\\foo{x}$ and [[missing]]"),
+                ("notes/b.md", "$x$"),
+            ],
+        );
+        for _ in 0..2 {
+            let report = Builder::new(site.source(), site.dest()).build().unwrap();
+            let mut warnings: Vec<String> = report
+                .warnings
+                .iter()
+                .map(|warning| {
+                    let name = warning.path.file_name().unwrap().to_string_lossy();
+                    format!("{}: {}", name, warning.message)
+                })
+                .collect();
+            warnings.sort();
+            assert_eq!(warnings.len(), 2, "{:?}", warnings);
+            assert!(warnings[0].starts_with("a.md: Unresolved wiki link [[missing]]"));
+            assert!(warnings[1].starts_with("a.md: Unsupported math $\\foo{x}$"));
+        }
+    }
+}
@@ -0,0 +1,765 @@
+use crate::config::{normalize_base_url, Site};
+use crate::file_type::Common;
+use crate::highlight::Highlighter;
+use crate::index::Index;
+use crate::manifest::{Entry, Manifest};
+use crate::util::RelativeFrom;
+use handlebars::Handlebars;
+use ignore::gitignore::Gitignore;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub use crate::builder::{Builder, Report};
+pub use crate::check::BrokenLink;
+pub use crate::config::Config;
+pub use crate::error::{Error, Failure, Result, Warning};
+pub use crate::file_type::{FileType, FileTypeFactory};
+
+mod builder;
+mod check;
+mod config;
+pub mod error;
+mod exclude;
+mod feed;
+pub mod file_type;
+mod front_matter;
+mod headings;
+mod highlight;
+mod index;
+mod links;
+mod listing;
+mod manifest;
+mod math;
+mod serve;
+mod sitemap;
+mod tags;
+#[cfg(test)]
+mod testing;
+mod util;
+mod watch;
+
+pub struct AppContext<'a> {
+    root_source: PathBuf,
+    root_dest: PathBuf,
+    root_notes: PathBuf,
+    handlebars: Handlebars<'a>,
+    template_hashes: HashMap<&'static str, String>,
+    base_url: String,
+    config: Config,
+    site: Site,
+    index: Index,
+    highlighter: Option<Highlighter>,
+    excludes: Gitignore,
+    warnings: Mutex<Vec<Warning>>,
+}
+
+// Read access for file types and factories outside of this crate
+impl<'a> AppContext<'a> {
+    pub fn root_source(&self) -> &Path {
+        &self.root_source
+    }
+
+    pub fn root_dest(&self) -> &Path {
+        &self.root_dest
+    }
+
+    pub fn root_notes(&self) -> &Path {
+        &self.root_notes
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn handlebars(&self) -> &Handlebars<'a> {
+        &self.handlebars
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    // Reports a problem with `path` that doesn't stop it from being built
+    pub fn warn(&self, path: &Path, message: String) {
+        let warning = Warning {
+            path: PathBuf::from(path),
+            message,
+        };
+        self.warnings.lock().unwrap().push(warning);
+    }
+
+    // Whether `path` is under one of the paths the config ignores, is hidden
+    // or matches `.notesignore` or the config's `exclude` patterns
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if self
+            .config
+            .ignore
+            .iter()
+            .any(|ignored| path.starts_with(self.root_source.join(ignored)))
+        {
+            return true;
+        }
+        let relative = match path.my_relative_from(&self.root_source) {
+            Some(relative) => relative,
+            None => return false,
+        };
+        (!self.config.hidden && exclude::is_hidden(relative))
+            || self
+                .excludes
+                .matched_path_or_any_parents(relative, path.is_dir())
+                .is_ignore()
+    }
+}
+
+// One source handled by a build job
+struct Built {
+    source: String,
+    entry: Entry,
+    written: bool,
+}
+
+// A page that doesn't come from one source file, e.g. a tag listing. `key`
+// stands in for the source in the manifest and `output` is relative to dest.
+struct Generated {
+    key: String,
+    output: PathBuf,
+    type_str: &'static str,
+    model: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct GeneratedModel<'m> {
+    #[serde(flatten)]
+    model: &'m serde_json::Value,
+    #[serde(flatten)]
+    common: Common<'m>,
+}
+
+// Builds the site described by a `Builder`, and keeps enough state around to
+// rebuild only what changed afterwards
+pub struct Generator<'a> {
+    context: AppContext<'a>,
+    file_type_manager: file_type::FileTypeManager,
+    clean: bool,
+    jobs: usize,
+    fail_fast: bool,
+    failures: Mutex<Vec<Failure>>,
+    report: Report,
+    // Hash of the config, outputs of a build with another one can't be reused
+    settings: String,
+    // What the last build wrote, and what this one has written so far
+    previous: Manifest,
+    manifest: Manifest,
+}
+
+impl<'a> Generator<'a> {
+    // Converts every path, `jobs` at a time. Destination directories are all
+    // created up front so the order files finish in doesn't matter.
+    // Directory introductions are left to their directory's page
+    fn convert_all(&mut self, paths: &[PathBuf]) -> Result<()> {
+        let paths: Vec<PathBuf> = paths
+            .iter()
+            .filter(|path| !file_type::is_intro(&self.context, path))
+            .cloned()
+            .collect();
+        if self.jobs > 1 {
+            for path in paths.iter().filter(|path| path.is_dir()) {
+                let relative = path.my_relative_from(&self.context.root_notes).unwrap();
+                let dest = self.context.root_dest.join(relative);
+                fs::create_dir_all(&dest).map_err(Error::io("Could not create", &dest))?;
+            }
+        }
+        let entries = self.run_jobs(&paths, |generator, path| generator.convert_entry(path))?;
+        for (path, built) in paths.iter().zip(entries) {
+            match built {
+                Some(built) => self.record(built),
+                None => self.keep_failed(self.source_key(path)),
+            }
+        }
+        Ok(())
+    }
+
+    fn convert_entry(&self, path: &Path) -> Result<Built> {
+        let file_type = self.file_type_manager.create_file_type(path)?;
+        let source = self.source_key(path);
+        let stamp = manifest::stamp(path);
+        let previous = self.previous.get(&source);
+        let hash = match previous {
+            Some(previous) if stamp.is_some() && previous.stamp == stamp => previous.hash.clone(),
+            _ => file_type.fingerprint(&self.context)?,
+        };
+        let dest = file_type.get_dest(&self.context);
+        let entry = Entry {
+            output: PathBuf::from(dest.my_relative_from(&self.context.root_dest).unwrap()),
+            hash,
+            site_hash: file_type.site_fingerprint(&self.context),
+            template_hash: self
+                .context
+                .template_hashes
+                .get(file_type.get_type_str())
+                .cloned()
+                .unwrap_or_default(),
+            stamp,
+        };
+        let written = previous != Some(&entry) || !dest.exists();
+        if written {
+            file_type.convert(&self.context)?;
+        }
+        Ok(Built {
+            source,
+            entry,
+            written,
+        })
+    }
+
+    fn copy_assets(&mut self, paths: &[PathBuf]) -> Result<()> {
+        let entries = self.run_jobs(paths, |generator, path| generator.copy_asset_entry(path))?;
+        for (path, built) in paths.iter().zip(entries) {
+            match built {
+                Some(Some(built)) => self.record(built),
+                Some(None) => (),
+                None => self.keep_failed(self.source_key(path)),
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, built: Built) {
+        if built.written {
+            self.report.written.push(built.entry.output.clone());
+        } else {
+            self.report.unchanged += 1;
+        }
+        self.manifest.insert(built.source, built.entry);
+    }
+
+    // A failed source keeps its last output, but with a hash that can't match
+    // so the next build tries it again
+    fn keep_failed(&mut self, source: String) {
+        if let Some(previous) = self.previous.get(&source) {
+            let mut entry = previous.clone();
+            entry.hash.clear();
+            self.manifest.insert(source, entry);
+        }
+    }
+
+    // Copies one asset, or creates the directory for one. Only files get a
+    // manifest entry.
+    fn copy_asset_entry(&self, path: &Path) -> Result<Option<Built>> {
+        let source = self.source_key(path);
+        let relative = path.my_relative_from(&self.context.root_source).unwrap();
+        let dest = self.context.root_dest.join(relative);
+        if path.is_dir() {
+            fs::create_dir_all(&dest).map_err(Error::io("Could not create", &dest))?;
+            return Ok(None);
+        }
+        let stamp = manifest::stamp(path);
+        let previous = self.previous.get(&source);
+        let hash = match previous {
+            Some(previous) if stamp.is_some() && previous.stamp == stamp => previous.hash.clone(),
+            _ => util::hash_file(path).map_err(Error::io("Could not read", path))?,
+        };
+        let entry = Entry {
+            output: PathBuf::from(relative),
+            hash,
+            template_hash: String::new(),
+            site_hash: String::new(),
+            stamp,
+        };
+        let written = previous != Some(&entry) || !dest.exists();
+        if written {
+            // Directories may not exist yet when copying in parallel
+            let parent = dest.parent().unwrap();
+            fs::create_dir_all(parent).map_err(Error::io("Could not create", parent))?;
+            fs::copy(path, &dest).map_err(Error::io("Could not copy", path))?;
+        }
+        Ok(Some(Built {
+            source,
+            entry,
+            written,
+        }))
+    }
+
+    // Maps `job` over `paths`, spread over `jobs` threads when asked to.
+    // Results keep the order of `paths` either way. Unless failing fast, a
+    // path whose job fails is recorded in `failures` and gives `None`.
+    fn run_jobs<T, F>(&self, paths: &[PathBuf], job: F) -> Result<Vec<Option<T>>>
+    where
+        T: Send,
+        F: Fn(&Self, &Path) -> Result<T> + Sync,
+    {
+        let attempt = |path: &PathBuf| self.settle(path, job(self, path));
+        if self.jobs <= 1 {
+            return paths.iter().map(attempt).collect();
+        }
+        let pool = match ThreadPoolBuilder::new().num_threads(self.jobs).build() {
+            Ok(pool) => pool,
+            // Not worth failing the build over, just go one at a time
+            Err(_) => return paths.iter().map(attempt).collect(),
+        };
+        pool.install(|| paths.par_iter().map(attempt).collect())
+    }
+
+    // Passes on a job's result, or records its failure and gives `None`
+    // unless failing fast
+    fn settle<T>(&self, path: &Path, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(result) => Ok(Some(result)),
+            Err(error) if self.fail_fast => Err(error),
+            Err(error) => {
+                let failure = Failure {
+                    path: PathBuf::from(path),
+                    error,
+                };
+                self.failures.lock().unwrap().push(failure);
+                Ok(None)
+            }
+        }
+    }
+
+    // Renders pages that aren't converted from a source, skipping those whose
+    // model and template are the same as last build
+    fn write_generated(&mut self, pages: Vec<Generated>) -> Result<()> {
+        for page in pages {
+            let dest = self.context.root_dest.join(&page.output);
+            let result = self.generated_entry(&page, &dest);
+            match self.settle(&dest, result)? {
+                Some(built) => self.record(built),
+                None => self.keep_failed(page.key),
+            }
+        }
+        Ok(())
+    }
+
+    fn generated_entry(&self, page: &Generated, dest: &Path) -> Result<Built> {
+        let entry = Entry {
+            output: page.output.clone(),
+            hash: util::hash_str(&page.model.to_string()),
+            template_hash: self
+                .context
+                .template_hashes
+                .get(page.type_str)
+                .cloned()
+                .unwrap_or_default(),
+            site_hash: String::new(),
+            stamp: None,
+        };
+        let written = self.previous.get(&page.key) != Some(&entry) || !dest.exists();
+        if written {
+            let model = GeneratedModel {
+                model: &page.model,
+                common: Common::new(&self.context),
+            };
+            let rendered = self
+                .context
+                .handlebars
+                .render(page.type_str, &model)
+                .map_err(|why| Error::Render {
+                    path: PathBuf::from(dest),
+                    source: Box::new(why),
+                })?;
+            let parent = dest.parent().unwrap();
+            fs::create_dir_all(parent).map_err(Error::io("Could not create", parent))?;
+            fs::write(dest, rendered).map_err(Error::io("Could not write", dest))?;
+        }
+        Ok(Built {
+            source: page.key.clone(),
+            entry,
+            written,
+        })
+    }
+
+    // First phase of a build: works out what's at each of `paths` and how
+    // notes link together, then renders the pages that only need that
+    fn index_site(&mut self, paths: &[PathBuf]) -> Result<()> {
+        let indexed = self.run_jobs(paths, |generator, path| {
+            let context = &generator.context;
+            Ok(index::index_path(
+                context,
+                &generator.file_type_manager,
+                path,
+            ))
+        })?;
+        self.context.index = Index::build(indexed.into_iter().flatten().flatten().collect());
+        // Warned about here rather than while converting, which unchanged
+        // notes skip
+        for (path, target) in self.context.index.unresolved() {
+            let message = format!("Unresolved wiki link [[{}]]", target);
+            self.context.warn(path, message);
+        }
+        let mut pages = tags::pages(&self.context);
+        pages.extend(feed::pages(&self.context));
+        pages.extend(sitemap::pages(&self.context));
+        pages.extend(highlight::pages(&self.context));
+        self.check_generated(&pages)?;
+        self.write_generated(pages)
+    }
+
+    // Notes are written to the root of dest like generated pages, so one
+    // could land on a tag page or feed and quietly replace it
+    fn check_generated(&self, pages: &[Generated]) -> Result<()> {
+        let outputs: HashSet<&Path> = pages.iter().map(|page| page.output.as_path()).collect();
+        let mut clashes: Vec<&PathBuf> = self
+            .context
+            .index
+            .nodes()
+            .filter(|(_, node)| outputs.contains(node.output.as_path()))
+            .map(|(path, _)| path)
+            .collect();
+        clashes.sort();
+        match clashes.first() {
+            Some(path) => Err(Error::InvalidSource {
+                path: PathBuf::from(path),
+                reason: "Would be written over a generated tag page, feed or sitemap",
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // Ends a build or rebuild, handing over what it did
+    fn take_report(&mut self) -> Report {
+        let mut report = std::mem::take(&mut self.report);
+        report.failures = std::mem::take(&mut *self.failures.lock().unwrap());
+        report.failures.sort_by(|a, b| a.path.cmp(&b.path));
+        report.warnings = std::mem::take(&mut *self.context.warnings.lock().unwrap());
+        report.warnings.sort_by(|a, b| a.path.cmp(&b.path));
+        report
+    }
+
+    // `walk_paths` without anything ignored or directories' listing files.
+    // Paths that aren't UTF-8 can't have a url, so they're skipped with a
+    // warning.
+    fn source_paths(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let skip = |path: &Path| {
+            if self.context.is_ignored(path) || listing::is_listing_file(path) {
+                return true;
+            }
+            if path.to_str().is_none() {
+                let message = String::from("Skipped, its name isn't valid UTF-8");
+                self.context.warn(path, message);
+                return true;
+            }
+            false
+        };
+        if skip(path) {
+            return Ok(Vec::new());
+        }
+        walk_paths(path, skip)
+    }
+
+    // Manifest key for a source path, relative to the source root
+    fn source_key(&self, path: &Path) -> String {
+        let relative = path.my_relative_from(&self.context.root_source).unwrap();
+        relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn new(builder: Builder) -> Result<Generator<'a>> {
+        let source_path = builder.source.as_path();
+        if !source_path.is_dir() {
+            return Err(Error::InvalidSource {
+                path: PathBuf::from(source_path),
+                reason: "Source is not a directory",
+            });
+        }
+
+        let dest_path = builder.dest.as_path();
+        if !dest_path.is_dir() {
+            fs::create_dir_all(dest_path).map_err(Error::io("Could not create", dest_path))?;
+        }
+
+        // Validate source
+        let notes_source_path = source_path.join("notes");
+        if !notes_source_path.is_dir() {
+            return Err(Error::InvalidSource {
+                path: notes_source_path,
+                reason: "Missing notes directory",
+            });
+        }
+
+        // Whatever the builder was given wins over the config file
+        let mut config = Config::load(source_path)?;
+        if let Some(ref base_url) = builder.base_url {
+            config.base_url = normalize_base_url(base_url);
+        }
+        if let Some(clean) = builder.clean {
+            config.output.clean = clean;
+        }
+        if let Some(jobs) = builder.jobs {
+            config.output.jobs = jobs;
+        }
+        if let Some(fail_fast) = builder.fail_fast {
+            config.output.fail_fast = fail_fast;
+        }
+        // How the build runs doesn't change what it writes, so `[output]` is
+        // left out and e.g. another `--jobs` can still reuse the last build
+        let written = Config {
+            output: config::Output::default(),
+            ..config.clone()
+        };
+        let settings =
+            util::hash_str(&serde_json::to_string(&written).expect("Could not serialize config"));
+
+        let mut context = AppContext {
+            root_source: PathBuf::from(source_path),
+            root_dest: PathBuf::from(dest_path),
+            root_notes: notes_source_path,
+            handlebars: Handlebars::new(),
+            template_hashes: HashMap::new(),
+            base_url: config.base_url.clone(),
+            site: Site::new(&config),
+            index: Index::default(),
+            highlighter: Highlighter::new(source_path, &config)?,
+            excludes: exclude::matcher(source_path, &config)?,
+            warnings: Mutex::new(Vec::new()),
+            config,
+        };
+
+        let file_type_manager = file_type::FileTypeManager::with_factories(builder.factories);
+        file_type_manager.initialize_app_context(&mut context)?;
+        tags::initialize(&mut context)?;
+        feed::initialize(&mut context)?;
+        sitemap::initialize(&mut context)?;
+        highlight::initialize(&mut context)?;
+
+        // Good to go! Let's return something good
+
+        let manifest = Manifest::new(&settings);
+        Ok(Generator {
+            clean: context.config.output.clean,
+            jobs: context.config.output.jobs,
+            fail_fast: context.config.output.fail_fast,
+            context,
+            file_type_manager,
+            settings,
+            failures: Mutex::new(Vec::new()),
+            report: Report::default(),
+            previous: Manifest::default(),
+            manifest,
+        })
+    }
+
+    // Re-reads layouts and partials, e.g. after one was edited while watching.
+    pub fn reload_templates(&mut self) -> Result<()> {
+        self.context.handlebars = Handlebars::new();
+        self.context.template_hashes.clear();
+        self.file_type_manager
+            .initialize_app_context(&mut self.context)?;
+        tags::initialize(&mut self.context)?;
+        feed::initialize(&mut self.context)?;
+        sitemap::initialize(&mut self.context)?;
+        highlight::initialize(&mut self.context)
+    }
+
+    // Builds the whole site, skipping whatever is unchanged since the last
+    // build into the same dest. Files that fail are listed in the report
+    // unless failing fast.
+    pub fn build(&mut self) -> Result<Report> {
+        self.context.site.touch();
+        let previous = if self.clean {
+            None
+        } else {
+            Manifest::load(&self.context.root_dest, &self.settings)
+        };
+        // Without a usable manifest there's no telling what in dest is ours
+        self.previous = match previous {
+            Some(previous) => previous,
+            None => {
+                self.clean_dest()?;
+                Manifest::default()
+            }
+        };
+        self.clean = false;
+        self.manifest = Manifest::new(&self.settings);
+
+        let assets_source_path = self.context.root_source.join("assets");
+        if assets_source_path.is_dir() {
+            let paths = self.source_paths(&assets_source_path)?;
+            self.copy_assets(&paths)?;
+        }
+        // Everything is indexed before anything is rendered, so each page
+        // can show what's elsewhere on the site
+        let paths = self.source_paths(&self.context.root_notes)?;
+        self.index_site(&paths)?;
+        self.convert_all(&paths)?;
+
+        self.remove_stale("");
+        let manifest = Manifest::new(&self.settings);
+        self.previous = std::mem::replace(&mut self.manifest, manifest);
+        self.previous.save(&self.context.root_dest)?;
+        Ok(self.take_report())
+    }
+
+    // Folds what was converted since the last build into the saved manifest,
+    // for rebuilds that only touched some files
+    fn save_manifest(&mut self) -> Result<Report> {
+        let manifest = Manifest::new(&self.settings);
+        let converted = std::mem::replace(&mut self.manifest, manifest);
+        self.previous.extend(converted);
+        self.previous.save(&self.context.root_dest)?;
+        Ok(self.take_report())
+    }
+
+    // Deletes outputs of sources under `scope`, or anywhere when it's empty,
+    // that are gone since the last build, along with any directories that
+    // leaves empty
+    fn remove_stale(&mut self, scope: &str) {
+        let prefix = format!("{}/", scope);
+        let mut stale: Vec<(String, PathBuf)> = self
+            .previous
+            .stale(&self.manifest)
+            .filter(|(key, _)| scope.is_empty() || *key == scope || key.starts_with(&prefix))
+            .map(|(key, entry)| (key.clone(), entry.output.clone()))
+            .collect();
+        stale.sort_by(|a, b| b.1.cmp(&a.1));
+        for (key, output) in stale {
+            self.previous.remove(&key);
+            let path = self.context.root_dest.join(&output);
+            if fs::remove_file(&path).is_err() {
+                continue;
+            }
+            self.report.removed.push(output);
+            let mut parent = path.parent();
+            while let Some(dir) = parent {
+                if dir == self.context.root_dest || fs::remove_dir(dir).is_err() {
+                    break;
+                }
+                parent = dir.parent();
+            }
+        }
+    }
+
+    fn clean_dest(&self) -> Result<()> {
+        let root_dest = &self.context.root_dest;
+        let entries = fs::read_dir(root_dest).map_err(Error::io("Could not list", root_dest))?;
+        for entry in entries {
+            let path = entry
+                .map_err(Error::io("Could not list", root_dest))?
+                .path();
+            if path.is_dir() {
+                fs::remove_dir_all(&path).map_err(Error::io("Could not remove", &path))?;
+            } else {
+                fs::remove_file(&path).map_err(Error::io("Could not remove", &path))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// `path` followed by everything below it that `skip` doesn't rule out,
+// parents before their children. Nothing below a skipped directory is
+// listed.
+fn walk_paths<F>(path: &Path, skip: F) -> Result<Vec<PathBuf>>
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut paths = vec![PathBuf::from(path)];
+    let mut i = 0;
+    while i < paths.len() {
+        let dir = paths[i].clone();
+        i += 1;
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&dir).map_err(Error::io("Could not list", &dir))? {
+            let child = entry.map_err(Error::io("Could not list", &dir))?.path();
+            if !skip(&child) {
+                paths.push(child);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestSite;
+
+    // Running the build differently mustn't throw away what the last one wrote
+    #[test]
+    fn output_options_keep_the_manifest() {
+        let site = TestSite::new("output-options", &[("notes/a.md", "# A")]);
+        let build = |builder: fn(Builder) -> Builder| {
+            builder(Builder::new(site.source(), site.dest()))
+                .build()
+                .unwrap()
+        };
+        let marker = site.dest().join("marker");
+        build(|builder| builder);
+        fs::write(&marker, "").unwrap();
+
+        let report = build(|builder| builder.jobs(4).fail_fast(true));
+        assert!(report.written.is_empty());
+        assert!(marker.exists());
+
+        build(|builder| builder.clean(true));
+        assert!(!marker.exists());
+        fs::write(&marker, "").unwrap();
+        let report = build(|builder| builder);
+        assert!(report.written.is_empty());
+        assert!(marker.exists());
+    }
+
+    #[test]
+    fn ignored_paths() {
+        let ignored = |name: &str, files: &[(&str, &str)], paths: &[&str]| {
+            let site = TestSite::new(name, files);
+            let generator = Builder::new(site.source(), site.dest())
+                .generator()
+                .unwrap();
+            let source = site.source();
+            paths
+                .iter()
+                .filter(|path| generator.context.is_ignored(&source.join(path)))
+                .map(|path| String::from(*path))
+                .collect::<Vec<_>>()
+        };
+        let paths = [
+            ".git/config",
+            "notes/.hidden.md",
+            "notes/a.md",
+            "notes/a.swp",
+            "notes/scratch/b.md",
+            "notes/drafts",
+            "notes/drafts/deep/c.md",
+            "assets/node_modules/x.js",
+            "assets/logo.png",
+        ];
+        let files = [
+            (".notesignore", "*.swp\nnotes/scratch/\n"),
+            (
+                "rust-notes.toml",
+                "ignore = [\"notes/drafts\"]\nexclude = [\"node_modules/\"]",
+            ),
+            ("notes/scratch/b.md", ""),
+            ("assets/node_modules/x.js", ""),
+        ];
+        assert_eq!(
+            ignored("ignored", &files, &paths),
+            [
+                ".git/config",
+                "notes/.hidden.md",
+                "notes/a.swp",
+                "notes/scratch/b.md",
+                "notes/drafts",
+                "notes/drafts/deep/c.md",
+                "assets/node_modules/x.js",
+            ]
+        );
+
+        let files = [
+            (".notesignore", "*.swp\n"),
+            ("rust-notes.toml", "hidden = true"),
+        ];
+        assert_eq!(ignored("ignored-hidden", &files, &paths), ["notes/a.swp"]);
+    }
+}
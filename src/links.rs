@@ -0,0 +1,414 @@
+use std::path::{Component, Path, PathBuf};
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+
+use crate::util::{percent_decode, slugify, RelativeFrom};
+use crate::AppContext;
+
+// A `[[target]]` or `[[target|label]]` link in a note
+struct WikiLink<'t> {
+    target: &'t str,
+    label: &'t str,
+}
+
+// Targets of every wiki link in a note's markdown, as written
+pub fn wiki_targets(text: &str, options: Options) -> Vec<String> {
+    wiki_targets_at(text, options)
+        .into_iter()
+        .map(|(target, _)| target)
+        .collect()
+}
+
+// Wiki link targets along with where in `text` each link starts
+pub fn wiki_targets_at(text: &str, options: Options) -> Vec<(String, usize)> {
+    // Runs of text are joined like `merge_text` does, remembering where
+    // each run started
+    let mut runs: Vec<(String, usize)> = Vec::new();
+    let mut joining = false;
+    let mut literal = 0;
+    for (event, range) in Parser::new_ext(text, options).into_offset_iter() {
+        literal = literal_depth(literal, &event);
+        match event {
+            Event::Text(ref text) if literal == 0 => match runs.last_mut() {
+                Some((run, _)) if joining => run.push_str(text),
+                _ => runs.push((text.to_string(), range.start)),
+            },
+            _ => {
+                joining = false;
+                continue;
+            }
+        }
+        joining = true;
+    }
+    let mut targets = Vec::new();
+    for (run, start) in runs.iter() {
+        let mut rest: &str = run;
+        while let Some((before, link, after)) = next_wiki_link(rest) {
+            let offset = run.len() - rest.len() + before.len();
+            targets.push((String::from(link.target), start + offset));
+            rest = after;
+        }
+    }
+    targets
+}
+
+// Replaces wiki links with anchors to wherever `resolve` says their target
+// is. Links it can't resolve get the `wiki-link-missing` class and no href.
+pub fn render_wiki_links<'e, F>(events: Vec<Event<'e>>, mut resolve: F) -> Vec<Event<'e>>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut result = Vec::with_capacity(events.len());
+    let mut literal = 0;
+    for event in merge_text(events) {
+        literal = literal_depth(literal, &event);
+        let text = match event {
+            Event::Text(text) if literal == 0 && text.contains("[[") => text,
+            event => {
+                result.push(event);
+                continue;
+            }
+        };
+        let mut rest: &str = &text;
+        while let Some((before, link, after)) = next_wiki_link(rest) {
+            if !before.is_empty() {
+                result.push(Event::Text(CowStr::from(String::from(before))));
+            }
+            let mut html = String::new();
+            match resolve(link.target) {
+                Some(url) => {
+                    html.push_str("<a class=\"wiki-link\" href=\"");
+                    escape_href(&mut html, &url).unwrap();
+                    html.push_str("\">");
+                }
+                None => html.push_str("<a class=\"wiki-link wiki-link-missing\">"),
+            }
+            escape_html(&mut html, link.label).unwrap();
+            html.push_str("</a>");
+            result.push(Event::Html(CowStr::from(html)));
+            rest = after;
+        }
+        if !rest.is_empty() {
+            result.push(Event::Text(CowStr::from(String::from(rest))));
+        }
+    }
+    result
+}
+
+// Destinations of every relative link and image in a note's markdown
+pub fn relative_hrefs(text: &str, options: Options) -> Vec<String> {
+    Parser::new_ext(text, options)
+        .filter_map(|event| match event {
+            Event::Start(Tag::Link(_, href, _)) | Event::Start(Tag::Image(_, href, _))
+                if is_relative(&href) =>
+            {
+                Some(href.to_string())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Points relative links and images at whatever `resolve` says the source
+// file they name turned into. Those it gives nothing for are left as written.
+pub fn render_relative_links<'e, F>(events: Vec<Event<'e>>, mut resolve: F) -> Vec<Event<'e>>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut rewrite = |tag: Tag<'e>| match tag {
+        Tag::Link(kind, href, title) if is_relative(&href) => match resolve(&href) {
+            Some(url) => Tag::Link(kind, CowStr::from(url), title),
+            None => Tag::Link(kind, href, title),
+        },
+        Tag::Image(kind, href, title) if is_relative(&href) => match resolve(&href) {
+            Some(url) => Tag::Image(kind, CowStr::from(url), title),
+            None => Tag::Image(kind, href, title),
+        },
+        tag => tag,
+    };
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(tag) => Event::Start(rewrite(tag)),
+            Event::End(tag) => Event::End(rewrite(tag)),
+            event => event,
+        })
+        .collect()
+}
+
+// Url of what a relative `href` in the note at `from` points to, keeping any
+// `?query` or `#fragment`. Only notes, directories and assets of the site
+// have one.
+pub fn source_url(context: &AppContext<'_>, from: &Path, href: &str) -> Option<String> {
+    let target = href_path(from, href)?;
+    let rest = &href[href.find(['?', '#']).unwrap_or(href.len())..];
+    let url = if let Some(node) = context.index.node(&target) {
+        // Headings in notes have slugs as ids, like `Index::resolve_url` links to
+        if context.index.get(&target).is_some() {
+            if let Some(hash) = rest.find('#') {
+                let fragment = slugify(&percent_decode(&rest[hash + 1..]));
+                return Some(format!("{}{}#{}", node.url, &rest[..hash], fragment));
+            }
+        }
+        node.url.clone()
+    } else {
+        let assets = context.root_source.join("assets");
+        let relative = target.my_relative_from(&assets)?;
+        if !target.is_file() || context.is_ignored(&target) {
+            return None;
+        }
+        let relative = relative.to_str()?.replace('\\', "/");
+        format!("{}assets/{}", context.base_url, relative)
+    };
+    Some(format!("{}{}", url, rest))
+}
+
+// Path of the file a relative `href` in the note at `from` names, in the
+// form the site index has them, whether or not there's such a file
+pub fn href_path(from: &Path, href: &str) -> Option<PathBuf> {
+    let path = &href[..href.find(['?', '#']).unwrap_or(href.len())];
+    if path.is_empty() {
+        return None;
+    }
+    Some(normalize(&from.parent()?.join(percent_decode(path))))
+}
+
+// Whether `href` is a path relative to the page, rather than a url with a
+// scheme, an absolute path or only a fragment
+pub fn is_relative(href: &str) -> bool {
+    if href.is_empty() || href.starts_with('/') || href.starts_with('#') {
+        return false;
+    }
+    // `mailto:` or `https:` before any path, query or fragment
+    let scheme_end = href.find([':', '/', '?', '#']);
+    !matches!(scheme_end, Some(end) if href[end..].starts_with(':'))
+}
+
+// Resolves `..` without touching the file system, so paths that don't exist
+// can be compared too. `components` already leaves out any `.` but a leading
+// one, which is kept like in the paths of the site index.
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    result.pop();
+                }
+                _ => result.push(component),
+            },
+            component => result.push(component),
+        }
+    }
+    result
+}
+
+// Text in code blocks, links and images is never a wiki link. Gives how
+// deep in those the next event is.
+fn literal_depth(depth: usize, event: &Event<'_>) -> usize {
+    match event {
+        Event::Start(Tag::CodeBlock(_))
+        | Event::Start(Tag::Link(..))
+        | Event::Start(Tag::Image(..)) => depth + 1,
+        Event::End(Tag::CodeBlock(_)) | Event::End(Tag::Link(..)) | Event::End(Tag::Image(..)) => {
+            depth - 1
+        }
+        _ => depth,
+    }
+}
+
+// The parser splits text at every bracket, so runs of text are joined
+// before looking for links
+fn merge_text<'e, I: IntoIterator<Item = Event<'e>>>(events: I) -> Vec<Event<'e>> {
+    let mut result: Vec<Event<'e>> = Vec::new();
+    for event in events {
+        if let Event::Text(ref text) = event {
+            if let Some(Event::Text(previous)) = result.last_mut() {
+                let mut joined = previous.to_string();
+                joined.push_str(text);
+                *previous = CowStr::from(joined);
+                continue;
+            }
+        }
+        result.push(event);
+    }
+    result
+}
+
+// Splits `text` around its first wiki link
+fn next_wiki_link(text: &str) -> Option<(&str, WikiLink<'_>, &str)> {
+    let mut offset = 0;
+    loop {
+        let start = offset + text[offset..].find("[[")?;
+        let inner_start = start + 2;
+        let end = inner_start + text[inner_start..].find("]]")?;
+        let inner = &text[inner_start..end];
+        // `[[` again before the closing brackets starts a new link
+        if let Some(nested) = inner.rfind("[[") {
+            offset = inner_start + nested;
+            continue;
+        }
+        let (target, label) = match inner.find('|') {
+            Some(bar) => (inner[..bar].trim(), inner[bar + 1..].trim()),
+            None => (inner.trim(), inner.trim()),
+        };
+        if target.is_empty() || inner.contains('\n') {
+            offset = end;
+            continue;
+        }
+        let link = WikiLink {
+            target,
+            label: if label.is_empty() { target } else { label },
+        };
+        return Some((&text[..start], link, &text[end + 2..]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::Builder;
+
+    // Target, label and the text after the first wiki link in `text`
+    fn first_link(text: &str) -> Option<(&str, &str, &str)> {
+        next_wiki_link(text).map(|(_, link, after)| (link.target, link.label, after))
+    }
+
+    #[test]
+    fn wiki_link_targets_and_labels() {
+        assert_eq!(first_link("see [[a]] b"), Some(("a", "a", " b")));
+        assert_eq!(first_link("[[ a | b ]]"), Some(("a", "b", "")));
+        assert_eq!(first_link("[[a|]]"), Some(("a", "a", "")));
+        assert_eq!(first_link("[[a#Heading|b]]"), Some(("a#Heading", "b", "")));
+        assert_eq!(first_link("[[a] b"), None);
+        assert_eq!(first_link("no links"), None);
+    }
+
+    #[test]
+    fn nested_wiki_links() {
+        let (before, link, after) = next_wiki_link("[[a [[b]] c]]").unwrap();
+        assert_eq!((before, link.target, after), ("[[a ", "b", " c]]"));
+        assert_eq!(first_link("[[[[a]]"), Some(("a", "a", "")));
+    }
+
+    #[test]
+    fn skipped_wiki_links() {
+        assert_eq!(first_link("[[]] [[a]]"), Some(("a", "a", "")));
+        assert_eq!(first_link("[[ |b]] [[a]]"), Some(("a", "a", "")));
+        assert_eq!(first_link("[[a\nb]] [[c]]"), Some(("c", "c", "")));
+        assert_eq!(first_link("[[]]"), None);
+    }
+
+    #[test]
+    fn wiki_links_in_code_and_links_are_text() {
+        let text = "[[a]] `[[b]]` [x [[c]]](d.md) ![[[e]]](f.png)\n\n```\n[[g]]\n```\n\n    [[h]]\n\n[[i]]";
+        assert_eq!(wiki_targets(text, Options::empty()), ["a", "i"]);
+    }
+
+    #[test]
+    fn wiki_link_positions() {
+        let text = "# Title\n\nSee [[a]] and *[[b|c]]*\n";
+        let targets = wiki_targets_at(text, Options::empty());
+        let found: Vec<(&str, &str)> = targets
+            .iter()
+            .map(|(target, start)| (target.as_str(), &text[*start..*start + 2]))
+            .collect();
+        assert_eq!(found, [("a", "[["), ("b", "[[")]);
+        assert_eq!(targets[0].1, text.find("[[a").unwrap());
+    }
+
+    #[test]
+    fn literal_depth_counts_code_blocks_links_and_images() {
+        let text = "[a ![b](c.png)](d.md) e\n\n```\nf\n```\n";
+        let mut depth = 0;
+        let depths: Vec<(String, usize)> = Parser::new(text)
+            .filter_map(|event| {
+                depth = literal_depth(depth, &event);
+                match event {
+                    Event::Text(text) => Some((text.to_string(), depth)),
+                    _ => None,
+                }
+            })
+            .collect();
+        let expected = [("a ", 1), ("b", 2), (" e", 0), ("f\n", 1)];
+        let expected: Vec<(String, usize)> = expected
+            .iter()
+            .map(|&(text, depth)| (String::from(text), depth))
+            .collect();
+        assert_eq!(depths, expected);
+        assert_eq!(depth, 0);
+    }
+
+    #[test]
+    fn normalize_keeps_a_leading_dot() {
+        let cases = [
+            ("./notes/sub/../a.md", "./notes/a.md"),
+            ("notes/./sub/./a.md", "notes/sub/a.md"),
+            ("../../site/notes/sub/../a.md", "../../site/notes/a.md"),
+            ("./notes/../../a.md", "./../a.md"),
+            ("/site/notes/sub/../../assets/a.png", "/site/assets/a.png"),
+        ];
+        for &(path, normalized) in cases.iter() {
+            assert_eq!(
+                normalize(Path::new(path)),
+                Path::new(normalized),
+                "{}",
+                path
+            );
+        }
+    }
+
+    // A site below the working directory, so it can be given as `./…` like
+    // `rust-notes . dest` does
+    #[test]
+    fn source_url_with_dot_relative_source() {
+        let root = PathBuf::from(format!("./target/links-test-{}", std::process::id()));
+        let source = root.join("site");
+        for &(path, contents) in [
+            ("partials/header.hbs", ""),
+            ("partials/footer.hbs", ""),
+            ("layouts/note.hbs", "{{{content}}}"),
+            ("layouts/dir.hbs", ""),
+            ("notes/a.md", "# A"),
+            ("notes/sub/b.md", "# B"),
+            ("assets/pic.png", ""),
+        ]
+        .iter()
+        {
+            let path = source.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let mut generator = Builder::new(&source, root.join("out"))
+            .base_url("notes")
+            .generator()
+            .unwrap();
+        generator.build().unwrap();
+        let context = &generator.context;
+        let from = source.join("notes/sub/b.md");
+        let url = |href: &str| source_url(context, &from, href);
+        assert_eq!(url("../a.md"), Some(String::from("/notes/a.html")));
+        assert_eq!(
+            url("./b.md?x#y"),
+            Some(String::from("/notes/sub/b.html?x#y"))
+        );
+        assert_eq!(
+            url("../a.md#Getting%20Started"),
+            Some(String::from("/notes/a.html#getting-started"))
+        );
+        assert_eq!(
+            url("../../assets/pic.png#Top"),
+            Some(String::from("/notes/assets/pic.png#Top"))
+        );
+        assert_eq!(url(".."), Some(String::from("/notes/")));
+        assert_eq!(
+            url("../../assets/pic.png"),
+            Some(String::from("/notes/assets/pic.png"))
+        );
+        assert_eq!(url("missing.md"), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
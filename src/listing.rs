@@ -0,0 +1,219 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::config::{Listing, Sort};
+use crate::index::Node;
+
+// Read from a directory to change how its page orders what's in it. It isn't
+// part of the site itself.
+pub static LISTING_FILE: &str = ".listing.toml";
+
+// A directory's own listing settings, over the config's `[listing]`
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirListing {
+    sort: Option<Sort>,
+    reverse: Option<bool>,
+    dirs_first: Option<bool>,
+    // Names that come first and in this order, before anything else
+    order: Vec<String>,
+}
+
+impl DirListing {
+    // `None` for directories without a listing file
+    pub fn load(dir: &Path) -> Option<std::result::Result<DirListing, String>> {
+        let path = dir.join(LISTING_FILE);
+        if !path.is_file() {
+            return None;
+        }
+        Some(
+            fs::read_to_string(&path)
+                .map_err(|why| why.to_string())
+                .and_then(|contents| toml::from_str(&contents).map_err(|why| why.to_string())),
+        )
+    }
+}
+
+pub fn is_listing_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == LISTING_FILE)
+}
+
+// Orders the nodes in a directory by the config's listing settings, or the
+// directory's own
+pub fn sort(children: &mut [&Node], listing: &Listing, dir: Option<&DirListing>) {
+    let sort = dir.and_then(|dir| dir.sort).unwrap_or(listing.sort);
+    let reverse = dir.and_then(|dir| dir.reverse).unwrap_or(listing.reverse);
+    let dirs_first = dir
+        .and_then(|dir| dir.dirs_first)
+        .unwrap_or(listing.dirs_first);
+    let order: &[String] = dir.map_or(&[], |dir| &dir.order);
+    let position = |node: &Node| order.iter().position(|name| *name == node.name);
+    children.sort_by(|a, b| match (position(a), position(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => {
+            let is_dir = |node: &Node| node.type_str == "dir";
+            let by_kind = if dirs_first {
+                is_dir(b).cmp(&is_dir(a))
+            } else {
+                Ordering::Equal
+            };
+            let by_sort = compare(sort, a, b);
+            by_kind.then(if reverse { by_sort.reverse() } else { by_sort })
+        }
+    });
+}
+
+fn compare(sort: Sort, a: &Node, b: &Node) -> Ordering {
+    let by_name = || natural(&a.name, &b.name).then_with(|| a.name.cmp(&b.name));
+    match sort {
+        Sort::Name => a.name.cmp(&b.name),
+        Sort::Natural => by_name(),
+        Sort::CaseInsensitive => a
+            .name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then_with(|| a.name.cmp(&b.name)),
+        Sort::Date => a.date.cmp(&b.date).then_with(by_name),
+        Sort::Weight => match (a.weight, b.weight) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+        .then_with(by_name),
+    }
+}
+
+// Like comparing byte for byte, except runs of digits compare as numbers, so
+// `note2` comes before `note10`
+fn natural(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (x, y) = match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => (x, y),
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let (x_digits, x_rest) = split_digits(a);
+            let (y_digits, y_rest) = split_digits(b);
+            let x_number = x_digits.trim_start_matches('0');
+            let y_number = y_digits.trim_start_matches('0');
+            let ordering = x_number
+                .len()
+                .cmp(&y_number.len())
+                .then_with(|| x_number.cmp(y_number))
+                .then_with(|| x_digits.len().cmp(&y_digits.len()));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            a = x_rest;
+            b = y_rest;
+        } else if x != y {
+            return x.cmp(&y);
+        } else {
+            a = &a[x.len_utf8()..];
+            b = &b[y.len_utf8()..];
+        }
+    }
+}
+
+fn split_digits(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn node(name: &str, type_str: &'static str) -> Node {
+        Node {
+            name: String::from(name),
+            url: format!("/{}", name),
+            type_str,
+            output: PathBuf::from(name),
+            date: None,
+            weight: None,
+            children: Vec::new(),
+            listing: None,
+        }
+    }
+
+    fn sorted(nodes: &[Node], listing: &str, dir: Option<&str>) -> Vec<String> {
+        let listing: Listing = toml::from_str(listing).unwrap();
+        let dir: Option<DirListing> = dir.map(|dir| toml::from_str(dir).unwrap());
+        let mut children: Vec<&Node> = nodes.iter().collect();
+        sort(&mut children, &listing, dir.as_ref());
+        children.iter().map(|node| node.name.clone()).collect()
+    }
+
+    fn notes(names: &[&str]) -> Vec<Node> {
+        names.iter().map(|name| node(name, "markdown")).collect()
+    }
+
+    #[test]
+    fn natural_compares_numbers() {
+        let nodes = notes(&["note10", "note2", "note1", "note"]);
+        assert_eq!(
+            sorted(&nodes, "sort = \"natural\"", None),
+            ["note", "note1", "note2", "note10"]
+        );
+        assert_eq!(
+            sorted(&nodes, "sort = \"name\"", None),
+            ["note", "note1", "note10", "note2"]
+        );
+    }
+
+    #[test]
+    fn natural_with_leading_zeros() {
+        assert_eq!(natural("note02", "note2"), Ordering::Greater);
+        assert_eq!(natural("note02", "note3"), Ordering::Less);
+        assert_eq!(natural("note002", "note010"), Ordering::Less);
+        assert_eq!(natural("0", "00"), Ordering::Less);
+        let nodes = notes(&["v010", "v9", "v02", "v2"]);
+        assert_eq!(
+            sorted(&nodes, "sort = \"natural\"", None),
+            ["v2", "v02", "v9", "v010"]
+        );
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let nodes = notes(&["banana", "Cherry", "apple", "Apple"]);
+        assert_eq!(
+            sorted(&nodes, "sort = \"case-insensitive\"", None),
+            ["Apple", "apple", "banana", "Cherry"]
+        );
+        assert_eq!(
+            sorted(&nodes, "sort = \"name\"", None),
+            ["Apple", "Cherry", "apple", "banana"]
+        );
+    }
+
+    #[test]
+    fn order_comes_first() {
+        let mut nodes = notes(&["a", "b", "c", "syllabus", "intro"]);
+        nodes.push(node("dir", "dir"));
+        let dir = "order = [\"syllabus\", \"intro\", \"missing\"]\nreverse = true";
+        assert_eq!(
+            sorted(&nodes, "", Some(dir)),
+            ["syllabus", "intro", "dir", "c", "b", "a"]
+        );
+        let dir = "order = [\"c\"]\ndirs_first = false";
+        assert_eq!(
+            sorted(&nodes, "", Some(dir)),
+            ["c", "a", "b", "dir", "intro", "syllabus"]
+        );
+    }
+}
@@ -1,180 +1,114 @@
-use crate::util::RelativeFrom;
 use docopt::Docopt;
-use handlebars::Handlebars;
+use rust_notes::{Builder, Error, Result};
 use serde::Deserialize;
-use std::fs;
-use std::path::{Path, PathBuf};
-
-mod file_type;
-mod util;
+use std::path::PathBuf;
+use std::{env, fs, process};
 
 // Docopt usage string
-static USAGE: &'static str = "
-Usage: rust-notes [options] <source> <dest>
+static USAGE: &str = "
+Usage:
+    rust-notes watch [options] <source> <dest>
+    rust-notes serve [options] <source> [<dest>]
+    rust-notes check [options] <source> [<dest>]
+    rust-notes [options] <source> <dest>
+
+Commands:
+    watch                   Build the site, then keep rebuilding changed files.
+    serve                   Like watch, but also serve the site on localhost and
+                            reload open pages after each rebuild. Builds into a
+                            temporary directory when no dest is given.
+    check                   Build the site, then report every link and image in
+                            the notes that leads nowhere. Builds into a
+                            temporary directory when no dest is given.
+
+Settings are read from rust-notes.toml in the source directory when it has one.
+Options given here take precedence over it.
 
 Options:
     -b, --base-url BASE     Base URL for site. Should not include hostname.
+    -c, --clean             Empty dest and rebuild everything instead of only
+                            what changed since the last build.
+    --fail-fast             Stop at the first file that fails to build instead
+                            of skipping it and reporting all failures at the end.
+    -j, --jobs N            Number of files to convert in parallel.
+    -p, --port PORT         Port for the preview server. [default: 4000]
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
+    cmd_watch: bool,
+    cmd_serve: bool,
+    cmd_check: bool,
     arg_source: String,
     arg_dest: String,
     flag_base_url: Option<String>,
+    flag_clean: bool,
+    flag_fail_fast: bool,
+    flag_jobs: Option<usize>,
+    flag_port: u16,
 }
 
 fn main() {
-    let args: Args = Docopt::new(USAGE)
+    let mut args: Args = Docopt::new(USAGE)
         .and_then(|d| d.deserialize())
         .unwrap_or_else(|e| e.exit());
-    //Generator(args).start();
-    match Generator::new(args) {
-        Ok(generator) => {
-            generator.begin();
-        }
-        Err(message) => panic!("{}", message),
-    }
-}
-
-fn cp_dir(source: &Path, dest: &Path) {
-    fs::create_dir(dest)
-        .ok()
-        .expect("Problem copying directory");
-    for item in util::walk_dir(source)
-        .ok()
-        .expect("Problem copying directory")
-    {
-        let item = item.ok().expect("Problem copying directory").path();
-        let item_metadata = fs::metadata(&item)
-            .ok()
-            .expect("Error fetching file metadata");
-        let relative = item.my_relative_from(source).unwrap();
-        let dest_path = dest.clone().join(&relative);
-        if item_metadata.is_file() {
-            fs::copy(&item, &dest_path)
-                .ok()
-                .expect("Problem copying directory");
-        } else {
-            fs::create_dir(&dest_path)
-                .ok()
-                .expect("Problem copying directory");
-        }
+    if let Err(why) = run(&mut args) {
+        eprintln!("Error: {}", why);
+        process::exit(why.exit_code());
     }
 }
 
-pub struct AppContext<'a> {
-    root_source: PathBuf,
-    root_dest: PathBuf,
-    root_notes: PathBuf,
-    handlebars: Handlebars<'a>,
-    base_url: String,
-}
+// A dest made up for `serve` or `check`, removed again once they're done
+struct TempDest(PathBuf);
 
-struct Generator<'a> {
-    context: AppContext<'a>,
-    file_type_manager: file_type::FileTypeManager,
+impl Drop for TempDest {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
 }
 
-impl<'a> Generator<'a> {
-    fn convert(&self, path: &Path) {
-        self.file_type_manager
-            .create_file_type(path)
-            .convert(&self.context);
+fn run(args: &mut Args) -> Result<()> {
+    let _temp_dest = if (args.cmd_serve || args.cmd_check) && args.arg_dest.is_empty() {
+        let temp_dest = env::temp_dir().join(format!("rust-notes-{}", process::id()));
+        fs::create_dir_all(&temp_dest).map_err(Error::io("Could not create", &temp_dest))?;
+        args.arg_dest = String::from(temp_dest.to_str().unwrap());
+        Some(TempDest(temp_dest))
+    } else {
+        None
+    };
+    // Flags that weren't given leave the config file's value alone
+    let mut builder = Builder::new(&args.arg_source, &args.arg_dest);
+    if let Some(ref base_url) = args.flag_base_url {
+        builder = builder.base_url(base_url);
     }
-
-    pub fn new(args: Args) -> Result<Generator<'a>, &'static str> {
-        let source_path = Path::new(&args.arg_source);
-        let source_path_metadata = fs::metadata(source_path).ok().expect(&format!(
-            "Error fetching file metadata. Source path: {:?}",
-            source_path
-        ));
-        let dest_path = Path::new(&args.arg_dest);
-        let dest_path_metadata = fs::metadata(dest_path).ok().expect(&format!(
-            "Error fetching file metadata. Dest path: {:?}",
-            dest_path
-        ));
-
-        if !source_path_metadata.is_dir() {
-            return Err("Invalid source path");
-        }
-
-        if !dest_path_metadata.is_dir() {
-            match fs::create_dir_all(&dest_path) {
-                Err(_) => return Err("Cannot create destination directory"),
-                _ => (),
-            }
-        }
-
-        // Validate source
-        let notes_source_path = source_path.join("notes");
-        let notes_source_path_metadata = fs::metadata(source_path)
-            .ok()
-            .expect("Error fetching file metadata");
-
-        if !notes_source_path_metadata.is_dir() {
-            return Err("Source directory missing required files");
-        }
-
-        let base_url = match args.flag_base_url {
-            Some(ref base_url) if base_url.is_empty() => None,
-            Some(base_url) => {
-                let mut result = String::from(base_url.trim_matches('/'));
-                result = format!("/{}/", result);
-                Some(result)
-            }
-            None => None,
-        };
-
-        let mut context = AppContext {
-            root_source: PathBuf::from(source_path),
-            root_dest: PathBuf::from(dest_path),
-            root_notes: notes_source_path,
-            handlebars: Handlebars::new(),
-            base_url: base_url.clone().unwrap_or(String::from("/")),
-        };
-
-        let file_type_manager = file_type::FileTypeManager::new();
-        file_type_manager.initialize_app_context(&mut context)?;
-
-        // Good to go! Let's return something good
-
-        Ok(Generator {
-            context: context,
-            file_type_manager: file_type_manager,
-        })
+    if args.flag_clean {
+        builder = builder.clean(true);
     }
-
-    pub fn begin(&self) {
-        self.clean_dest();
-        let assets_source_path = self.context.root_source.join("assets");
-        let assets_source_path_metadata = fs::metadata(&assets_source_path)
-            .ok()
-            .expect("Error fetching file metadata");
-        if assets_source_path_metadata.is_dir() {
-            let assets_dest_path = self.context.root_dest.join("assets");
-            cp_dir(&assets_source_path, &assets_dest_path);
-        }
-        self.convert(&self.context.root_notes);
-        for item in util::walk_dir(&self.context.root_notes).ok().unwrap() {
-            self.convert(&item.ok().unwrap().path());
-        }
+    if let Some(jobs) = args.flag_jobs {
+        builder = builder.jobs(jobs);
     }
-
-    fn clean_dest(&self) {
-        for entry in fs::read_dir(&self.context.root_dest).ok().unwrap() {
-            let entry = entry.ok().unwrap();
-            let entry_metadata = fs::metadata(entry.path())
-                .ok()
-                .expect("Error fetching file metadata");
-            if entry_metadata.is_file() {
-                fs::remove_file(entry.path())
-                    .ok()
-                    .expect("Could not remove file");
-            } else {
-                fs::remove_dir_all(entry.path())
-                    .ok()
-                    .expect("Could not remove directory");
-            }
-        }
+    if args.flag_fail_fast {
+        builder = builder.fail_fast(true);
+    }
+    let mut generator = builder.generator()?;
+    let report = generator.build()?;
+    for warning in report.warnings.iter() {
+        eprintln!("Warning: {}", warning);
+    }
+    if args.cmd_check {
+        report.into_result()?;
+        return generator.check();
+    }
+    if !args.cmd_serve && !args.cmd_watch {
+        return report.into_result().map(|_| ());
+    }
+    // Files that failed can still be fixed while watching
+    if let Err(why) = report.into_result() {
+        eprintln!("Error: {}", why);
+    }
+    if args.cmd_serve {
+        generator.serve(args.flag_port)
+    } else {
+        generator.watch()
     }
 }
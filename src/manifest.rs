@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+static MANIFEST_FILE: &str = ".rust-notes-manifest.json";
+
+// Record of what the last build wrote to dest, keyed by source path relative
+// to the source root. Lets later builds skip anything whose inputs are the
+// same and delete outputs whose sources went away.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Manifest {
+    version: String,
+    // Hash of the config the build ran with
+    settings: String,
+    entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Entry {
+    pub output: PathBuf,
+    pub hash: String,
+    pub template_hash: String,
+    // Hash of what the output takes from the rest of the site
+    pub site_hash: String,
+    // Size and modification time of a source file, used to reuse `hash`
+    // without reading the file again
+    pub stamp: Option<(u64, u64, u32)>,
+}
+
+impl Manifest {
+    pub fn new(settings: &str) -> Manifest {
+        Manifest {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            settings: String::from(settings),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    // Loads the manifest of the last build. A missing or unreadable manifest,
+    // or one written with different settings, means nothing can be reused.
+    pub fn load(dest: &Path, settings: &str) -> Option<Manifest> {
+        let contents = fs::read_to_string(dest.join(MANIFEST_FILE)).ok()?;
+        let manifest: Manifest = serde_json::from_str(&contents).ok()?;
+        if manifest.version == env!("CARGO_PKG_VERSION") && manifest.settings == settings {
+            Some(manifest)
+        } else {
+            None
+        }
+    }
+
+    pub fn save(&self, dest: &Path) -> Result<()> {
+        let path = dest.join(MANIFEST_FILE);
+        let contents = serde_json::to_string_pretty(self).expect("Could not serialize manifest");
+        fs::write(&path, contents).map_err(Error::io("Could not write", &path))
+    }
+
+    pub fn get(&self, source: &str) -> Option<&Entry> {
+        self.entries.get(source)
+    }
+
+    pub fn insert(&mut self, source: String, entry: Entry) {
+        self.entries.insert(source, entry);
+    }
+
+    pub fn extend(&mut self, other: Manifest) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn remove(&mut self, source: &str) {
+        self.entries.remove(source);
+    }
+
+    // Drops the entry for `source` and, for directories, everything below it
+    pub fn remove_tree(&mut self, source: &str) {
+        let prefix = format!("{}/", source);
+        self.entries
+            .retain(|key, _| key != source && !key.starts_with(&prefix));
+    }
+
+    // Entries of `self` that `current` no longer has, unless another of its
+    // sources now writes the same output
+    pub fn stale<'a>(
+        &'a self,
+        current: &'a Manifest,
+    ) -> impl Iterator<Item = (&'a String, &'a Entry)> + 'a {
+        let outputs: HashSet<&PathBuf> = current
+            .entries
+            .values()
+            .map(|entry| &entry.output)
+            .collect();
+        self.entries.iter().filter(move |(key, entry)| {
+            !current.entries.contains_key(*key) && !outputs.contains(&entry.output)
+        })
+    }
+}
+
+pub fn stamp(path: &Path) -> Option<(u64, u64, u32)> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_secs(), modified.subsec_nanos()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn entry(output: &str) -> Entry {
+        Entry {
+            output: PathBuf::from(output),
+            hash: String::from("hash"),
+            template_hash: String::new(),
+            site_hash: String::new(),
+            stamp: None,
+        }
+    }
+
+    fn manifest(entries: &[(&str, &str)]) -> Manifest {
+        let mut manifest = Manifest::new("settings");
+        for &(source, output) in entries.iter() {
+            manifest.insert(String::from(source), entry(output));
+        }
+        manifest
+    }
+
+    fn keys(manifest: &Manifest) -> Vec<&str> {
+        manifest.entries.keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn stale_entries() {
+        let previous = manifest(&[
+            ("notes/a.md", "notes/a.html"),
+            ("notes/b.md", "notes/b.html"),
+            ("notes/c.md", "notes/c.html"),
+            ("notes/d/index.md", "notes/d/index.html"),
+        ]);
+        // `b.md` is gone, `c.md` became `c.markdown` and `d`'s introduction
+        // is now written by the directory itself
+        let current = manifest(&[
+            ("notes/a.md", "notes/a.html"),
+            ("notes/c.markdown", "notes/c.html"),
+            ("notes/d", "notes/d/index.html"),
+        ]);
+        let stale: Vec<&str> = previous
+            .stale(&current)
+            .map(|(key, _)| key.as_str())
+            .collect();
+        assert_eq!(stale, ["notes/b.md"]);
+        assert_eq!(current.stale(&current).count(), 0);
+    }
+
+    #[test]
+    fn remove_tree() {
+        let mut manifest = manifest(&[
+            ("assets/img", "assets/img"),
+            ("assets/img/a.png", "assets/img/a.png"),
+            ("assets/img/sub/b.png", "assets/img/sub/b.png"),
+            ("assets/imgs/c.png", "assets/imgs/c.png"),
+            ("assets/style.css", "assets/style.css"),
+        ]);
+        manifest.remove_tree("assets/img");
+        assert_eq!(keys(&manifest), ["assets/imgs/c.png", "assets/style.css"]);
+        manifest.remove_tree("assets/style.css");
+        assert_eq!(keys(&manifest), ["assets/imgs/c.png"]);
+    }
+
+    #[test]
+    fn load_checks_settings_and_version() {
+        let dest = env::temp_dir().join(format!("rust-notes-manifest-{}", std::process::id()));
+        fs::create_dir_all(&dest).unwrap();
+        manifest(&[("notes/a.md", "notes/a.html")])
+            .save(&dest)
+            .unwrap();
+        let loaded = Manifest::load(&dest, "settings").unwrap();
+        assert_eq!(keys(&loaded), ["notes/a.md"]);
+        assert!(Manifest::load(&dest, "other settings").is_none());
+
+        let mut old = manifest(&[("notes/a.md", "notes/a.html")]);
+        old.version = String::from("0.0.0");
+        old.save(&dest).unwrap();
+        assert!(Manifest::load(&dest, "settings").is_none());
+
+        fs::write(dest.join(MANIFEST_FILE), "{").unwrap();
+        assert!(Manifest::load(&dest, "settings").is_none());
+        fs::remove_dir_all(&dest).unwrap();
+    }
+}
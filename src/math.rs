@@ -0,0 +1,271 @@
+use std::ops::Range;
+
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+use pulldown_latex::config::DisplayMode;
+use pulldown_latex::{push_mathml, RenderConfig, Storage};
+
+// Stand in for formulas while markdown is parsed. Private use characters
+// mean nothing to markdown, so it leaves them and the number between alone.
+const OPEN: char = '\u{e000}';
+const CLOSE: char = '\u{e001}';
+
+// A `$inline$` or `$$display$$` formula
+pub struct Formula {
+    latex: String,
+    display: bool,
+}
+
+// Takes formulas out of a note's markdown before it's parsed, so their
+// backslashes and underscores don't get read as markdown. Code is left as
+// it is.
+pub fn extract(text: &str, options: Options) -> (String, Vec<Formula>) {
+    if !text.contains('$') {
+        return (String::from(text), Vec::new());
+    }
+    let code: Vec<Range<usize>> = Parser::new_ext(text, options)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) | Event::Html(_) => Some(range),
+            _ => None,
+        })
+        .collect();
+    let in_code = |i: usize| code.iter().any(|range| range.contains(&i));
+
+    let mut result = String::with_capacity(text.len());
+    let mut formulas = Vec::new();
+    let mut copied = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if let Some(escaped) = rest.strip_prefix('\\') {
+            i += 1 + escaped.chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+        if !rest.starts_with('$') || in_code(i) {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+            continue;
+        }
+        let display = rest.starts_with("$$");
+        let delimiter = if display { "$$" } else { "$" };
+        let start = i + delimiter.len();
+        let end = match closing(text, start, display) {
+            Some(end) if !(start..end).any(&in_code) => end,
+            _ => {
+                i = start;
+                continue;
+            }
+        };
+        result.push_str(&text[copied..i]);
+        result.push(OPEN);
+        result.push_str(&formulas.len().to_string());
+        result.push(CLOSE);
+        formulas.push(Formula {
+            latex: String::from(text[start..end].trim()),
+            display,
+        });
+        i = end + delimiter.len();
+        copied = i;
+    }
+    result.push_str(&text[copied..]);
+    (result, formulas)
+}
+
+// Where the formula starting at `start` ends. Like in pandoc, inline ones
+// can't start or end with a space or be followed by a digit, so prices like
+// `$5 and $10` stay text. An inline one ends at the next `$`, and when that
+// can't close it the opening `$` is only a dollar sign. Neither goes past the
+// end of a paragraph.
+fn closing(text: &str, start: usize, display: bool) -> Option<usize> {
+    let rest = &text[start..];
+    if !display && rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let paragraph_end = rest.find("\n\n").unwrap_or(rest.len());
+    let mut i = 0;
+    while i < paragraph_end {
+        let tail = &rest[i..];
+        if let Some(escaped) = tail.strip_prefix('\\') {
+            i += 1 + escaped.chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+        if display && tail.starts_with("$$") {
+            return Some(start + i).filter(|_| i > 0);
+        }
+        if !display && tail.starts_with('$') {
+            let before = rest[..i].chars().next_back();
+            let after = tail[1..].chars().next();
+            let closes = i > 0
+                && before.is_some_and(|c| !c.is_whitespace())
+                && !after.is_some_and(|c| c.is_ascii_digit());
+            return Some(start + i).filter(|_| closes);
+        }
+        i += tail.chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+// Puts MathML where `extract` left placeholders. Formulas with `problems`
+// show up in the page as MathML errors.
+pub fn render_formulas<'e>(events: Vec<Event<'e>>, formulas: &[Formula]) -> Vec<Event<'e>> {
+    if formulas.is_empty() {
+        return events;
+    }
+    let mut result = Vec::with_capacity(events.len());
+    for event in events {
+        let text = match event {
+            Event::Text(ref text) if text.contains(OPEN) => text.to_string(),
+            event => {
+                result.push(event);
+                continue;
+            }
+        };
+        let mut rest: &str = &text;
+        while let Some(open) = rest.find(OPEN) {
+            let close = match rest[open..].find(CLOSE) {
+                Some(close) => open + close,
+                None => break,
+            };
+            let formula = match rest[open + OPEN.len_utf8()..close].parse::<usize>() {
+                Ok(n) if n < formulas.len() => &formulas[n],
+                _ => break,
+            };
+            if open > 0 {
+                result.push(Event::Text(CowStr::from(String::from(&rest[..open]))));
+            }
+            result.push(Event::Html(CowStr::from(mathml(formula))));
+            rest = &rest[close + CLOSE.len_utf8()..];
+        }
+        if !rest.is_empty() {
+            result.push(Event::Text(CowStr::from(String::from(rest))));
+        }
+    }
+    unwrap_display(result)
+}
+
+// Commands or environments in `formulas` that can't be turned into MathML,
+// as messages naming the formula
+pub fn problems(formulas: &[Formula]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for formula in formulas {
+        let storage = Storage::new();
+        for event in pulldown_latex::Parser::new(&formula.latex, &storage) {
+            if let Err(why) = event {
+                // The rest of the message is the formula again, pointing at
+                // the problem
+                let why = why.to_string();
+                let why = why.lines().next().unwrap_or_default();
+                let delimiter = if formula.display { "$$" } else { "$" };
+                problems.push(format!(
+                    "Unsupported math {}{}{}: {}",
+                    delimiter, formula.latex, delimiter, why
+                ));
+            }
+        }
+    }
+    problems
+}
+
+fn mathml(formula: &Formula) -> String {
+    let storage = Storage::new();
+    let events: Vec<_> = pulldown_latex::Parser::new(&formula.latex, &storage).collect();
+    let config = RenderConfig {
+        display_mode: if formula.display {
+            DisplayMode::Block
+        } else {
+            DisplayMode::Inline
+        },
+        ..RenderConfig::default()
+    };
+    let mut html = String::new();
+    push_mathml(&mut html, events.into_iter(), config).expect("Could not write MathML");
+    // Operators are written as they are, e.g. `<mo><</mo>` for `a < b`
+    html.replace("><</", ">&lt;</").replace(">&</", ">&amp;</")
+}
+
+// A display formula that's a paragraph of its own doesn't need the `<p>`
+fn unwrap_display(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut result: Vec<Event<'_>> = Vec::with_capacity(events.len());
+    for event in events {
+        if let Event::End(Tag::Paragraph) = event {
+            let len = result.len();
+            let alone = len >= 2
+                && matches!(result[len - 2], Event::Start(Tag::Paragraph))
+                && matches!(result[len - 1], Event::Html(ref html)
+                    if html.starts_with("<math display=\"block\""));
+            if alone {
+                let display = result.pop().unwrap();
+                result.pop();
+                result.push(display);
+                continue;
+            }
+        }
+        result.push(event);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extracted(text: &str) -> (String, Vec<String>) {
+        let (text, formulas) = extract(text, Options::empty());
+        (text, formulas.into_iter().map(|f| f.latex).collect())
+    }
+
+    #[test]
+    fn prices_stay_text() {
+        let text = "Costs $5 and $10 each, or $ 20 for $3.";
+        assert_eq!(extracted(text), (String::from(text), Vec::new()));
+    }
+
+    #[test]
+    fn price_before_a_formula() {
+        let (text, formulas) = extracted("Costs $5 each, and the area is $\\pi r^2$.");
+        assert_eq!(formulas, vec!["\\pi r^2"]);
+        assert_eq!(text, "Costs $5 each, and the area is \u{e000}0\u{e001}.");
+    }
+
+    #[test]
+    fn escaped_dollars() {
+        let (text, formulas) = extracted("From \\$5 to \\$10 and $x$");
+        assert_eq!(formulas, vec!["x"]);
+        assert!(text.starts_with("From \\$5 to \\$10 and "));
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        let text = "Use `$x$` here\n\n```\n$y$\n```\n\nand $z$ there";
+        let (_, formulas) = extracted(text);
+        assert_eq!(formulas, vec!["z"]);
+    }
+
+    #[test]
+    fn display_formulas() {
+        let (_, formulas) = extract("$$\n\\frac{a}{b}\n$$\n\nand $a$", Options::empty());
+        let display: Vec<(&str, bool)> = formulas
+            .iter()
+            .map(|f| (f.latex.as_str(), f.display))
+            .collect();
+        assert_eq!(display, vec![("\\frac{a}{b}", true), ("a", false)]);
+    }
+
+    #[test]
+    fn unsupported_commands() {
+        let text = "$x^2$ and $\\foo{x}$ and $$\\frac{a}{b}$$";
+        let (_, formulas) = extract(text, Options::empty());
+        let problems = problems(&formulas);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(
+            problems[0].starts_with("Unsupported math $\\foo{x}$: "),
+            "{}",
+            problems[0]
+        );
+    }
+
+    #[test]
+    fn formulas_end_with_their_paragraph() {
+        let (_, formulas) = extracted("$a\n\nb$");
+        assert!(formulas.is_empty());
+    }
+}
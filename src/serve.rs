@@ -0,0 +1,163 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::error::{Error, Result};
+use crate::{util, watch, Generator};
+
+// Lives outside of any base url so it can't collide with a generated page
+static RELOAD_PATH: &str = "/__rust-notes/reload";
+
+static RELOAD_SCRIPT: &str = "<script>new EventSource(\"/__rust-notes/reload\")\
+    .onmessage = function () { location.reload(); };</script>";
+
+type Clients = Arc<Mutex<Vec<Sender<()>>>>;
+
+impl<'a> Generator<'a> {
+    // Serves dest on localhost while watching, reloading open pages after
+    // every rebuild
+    pub fn serve(&mut self, port: u16) -> Result<()> {
+        serve(self, port)
+    }
+}
+
+fn serve(generator: &mut Generator<'_>, port: u16) -> Result<()> {
+    let address = format!("127.0.0.1:{}", port);
+    let server = Server::http(&address).map_err(|why| Error::Serve {
+        address: address.clone(),
+        message: why.to_string(),
+    })?;
+    let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+    let root = generator.context.root_dest.clone();
+    let base_url = generator.context.base_url.clone();
+    println!(
+        "Serving {:?} at http://localhost:{}{}",
+        root, port, base_url
+    );
+
+    let server_clients = clients.clone();
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            handle(request, &root, &base_url, &server_clients);
+        }
+    });
+
+    watch::watch(generator, || {
+        clients
+            .lock()
+            .unwrap()
+            .retain(|client| client.send(()).is_ok());
+    })
+}
+
+fn handle(request: Request, root: &Path, base_url: &str, clients: &Clients) {
+    let url = util::percent_decode(request.url().split('?').next().unwrap());
+    if url == RELOAD_PATH {
+        let (tx, rx) = channel();
+        clients.lock().unwrap().push(tx);
+        thread::spawn(move || stream_reloads(request, rx));
+        return;
+    }
+
+    let relative = match url.strip_prefix(base_url) {
+        Some(relative) => relative,
+        None if url == "/" || format!("{}/", url) == base_url => {
+            return redirect(request, base_url)
+        }
+        None => return not_found(request),
+    };
+    let relative = Path::new(relative);
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return not_found(request);
+    }
+
+    let mut path = root.join(relative);
+    if path.is_dir() {
+        if !url.ends_with('/') {
+            return redirect(request, &format!("{}/", url));
+        }
+        path.push("index.html");
+    }
+    match fs::read(&path) {
+        Ok(mut contents) => {
+            let content_type = content_type(&path);
+            if content_type.starts_with("text/html") {
+                contents = inject_reload_script(contents);
+            }
+            let header = Header::from_bytes(&b"Content-Type"[..], content_type).unwrap();
+            let _ = request.respond(Response::from_data(contents).with_header(header));
+        }
+        Err(_) => not_found(request),
+    }
+}
+
+// Holds an event stream open, sending a message whenever the site was
+// rebuilt. The timeout doubles as a keep-alive so closed tabs are noticed.
+fn stream_reloads(request: Request, rx: Receiver<()>) {
+    let mut writer = request.into_writer();
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+        Cache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+    if writer.write_all(head.as_bytes()).is_err() {
+        return;
+    }
+    loop {
+        let message = match rx.recv_timeout(Duration::from_secs(15)) {
+            Ok(()) => "data: reload\n\n",
+            Err(RecvTimeoutError::Timeout) => ": keep-alive\n\n",
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if writer.write_all(message.as_bytes()).is_err() || writer.flush().is_err() {
+            return;
+        }
+    }
+}
+
+fn inject_reload_script(contents: Vec<u8>) -> Vec<u8> {
+    let mut html = match String::from_utf8(contents) {
+        Ok(html) => html,
+        Err(why) => return why.into_bytes(),
+    };
+    match html.rfind("</body>") {
+        Some(index) => html.insert_str(index, RELOAD_SCRIPT),
+        None => html.push_str(RELOAD_SCRIPT),
+    }
+    html.into_bytes()
+}
+
+fn redirect(request: Request, location: &str) {
+    let header = Header::from_bytes(&b"Location"[..], location.as_bytes()).unwrap();
+    let _ = request.respond(Response::empty(302).with_header(header));
+}
+
+fn not_found(request: Request) {
+    let _ = request.respond(Response::from_string("404 Not Found").with_status_code(404));
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "application/javascript",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("txt") | Some("md") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("ico") => "image/x-icon",
+        Some("pdf") => "application/pdf",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
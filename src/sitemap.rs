@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use chrono::SecondsFormat;
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::file_type::{is_intro, register_template};
+use crate::{AppContext, Generated};
+
+static TYPE_STR: &str = "sitemap";
+
+// Written to the root of dest, also its manifest key
+pub static SITEMAP_FILE: &str = "sitemap.xml";
+
+static TEMPLATE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+{{#each urls}}<url>
+<loc>{{loc}}</loc>
+{{#if lastmod}}<lastmod>{{lastmod}}</lastmod>
+{{/if}}</url>
+{{/each}}</urlset>
+"#;
+
+pub fn initialize(app_context: &mut AppContext<'_>) -> Result<()> {
+    register_template(app_context, TYPE_STR, String::from(TEMPLATE))
+}
+
+// Every note and directory page except private ones. Like feeds, only for
+// sites with a hostname.
+pub fn pages(context: &AppContext<'_>) -> Vec<Generated> {
+    let hostname = match context.config.hostname {
+        Some(ref hostname) => hostname,
+        None => return Vec::new(),
+    };
+    let mut urls: Vec<Url> = context
+        .index
+        .nodes()
+        .filter(|(_, node)| node.type_str == "markdown" || node.type_str == "dir")
+        .filter(|(path, _)| !context.index.get(path).is_some_and(|note| note.private))
+        // Directory introductions are on their directory's page
+        .filter(|(path, _)| !is_intro(context, path))
+        .map(|(_, node)| Url {
+            loc: format!("{}{}", hostname, node.url),
+            lastmod: node
+                .date
+                .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        })
+        .collect();
+    urls.sort_by(|a, b| a.loc.cmp(&b.loc));
+    vec![Generated {
+        key: String::from(SITEMAP_FILE),
+        output: PathBuf::from(SITEMAP_FILE),
+        type_str: TYPE_STR,
+        model: serde_json::to_value(SitemapModel { urls }).expect("Could not serialize sitemap"),
+    }]
+}
+
+#[derive(Serialize)]
+struct SitemapModel {
+    urls: Vec<Url>,
+}
+
+#[derive(Serialize)]
+struct Url {
+    loc: String,
+    lastmod: Option<String>,
+}
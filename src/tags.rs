@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::file_type::{create_parent_links, read_layout, read_partial, register_template, Link};
+use crate::index::{IndexedNote, NoteLink};
+use crate::util::slugify;
+use crate::{AppContext, Generated};
+
+static TAGS_TYPE_STR: &str = "tags";
+static TAG_TYPE_STR: &str = "tag";
+
+// Where tag pages go in dest, and their manifest key. As a key it can't clash
+// with a source, those all start with `notes/` or `assets/`, but notes are
+// written to the root of dest so `notes/tags/` would land on it. Builds stop
+// with an error when that happens.
+pub static TAGS_DIR: &str = "tags";
+
+// Tag pages are optional, sites without `layouts/tags.hbs` and
+// `layouts/tag.hbs` don't get any
+pub fn initialize(app_context: &mut AppContext<'_>) -> Result<()> {
+    let layouts = app_context
+        .root_source
+        .join(&app_context.config.templates.layouts);
+    if !layouts.join("tags.hbs").is_file() || !layouts.join("tag.hbs").is_file() {
+        return Ok(());
+    }
+    let header_hbs_contents = read_partial(app_context, "header.hbs")?;
+    let footer_hbs_contents = read_partial(app_context, "footer.hbs")?;
+    for &(name, layout) in [(TAGS_TYPE_STR, "tags.hbs"), (TAG_TYPE_STR, "tag.hbs")].iter() {
+        let layout_hbs_contents = read_layout(app_context, layout)?;
+        register_template(
+            app_context,
+            name,
+            format!(
+                "{}\n{}\n{}",
+                header_hbs_contents, layout_hbs_contents, footer_hbs_contents
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn tag_url(base_url: &str, tag: &str) -> String {
+    format!("{}{}/{}/", base_url, TAGS_DIR, slugify(tag))
+}
+
+// Whether the site has the layouts tag pages need
+fn has_pages(context: &AppContext<'_>) -> bool {
+    context.template_hashes.contains_key(TAG_TYPE_STR)
+}
+
+// A tag of a note, with the url of its page when the site has tag pages
+#[derive(Serialize)]
+pub struct TagLink {
+    name: String,
+    url: Option<String>,
+}
+
+// A note's tags, linking to their pages if there are any
+pub fn tag_links(context: &AppContext<'_>, tags: &[String]) -> Vec<TagLink> {
+    tags.iter()
+        .filter(|tag| !slugify(tag).is_empty())
+        .map(|tag| TagLink {
+            name: tag.clone(),
+            url: Some(tag_url(&context.base_url, tag)).filter(|_| has_pages(context)),
+        })
+        .collect()
+}
+
+// The `/tags/` index and one page per tag, for every tag in the index
+pub fn pages(context: &AppContext<'_>) -> Vec<Generated> {
+    if !has_pages(context) {
+        return Vec::new();
+    }
+    // Tags that only differ in case or punctuation share a page, named after
+    // the first spelling seen
+    let mut tags: BTreeMap<String, (String, Vec<&IndexedNote>)> = BTreeMap::new();
+    for note in context.index.notes.iter() {
+        for tag in note.tags.iter() {
+            let slug = slugify(tag);
+            if slug.is_empty() {
+                continue;
+            }
+            tags.entry(slug)
+                .or_insert_with(|| (tag.clone(), Vec::new()))
+                .1
+                .push(note);
+        }
+    }
+
+    let tags_path = PathBuf::from(TAGS_DIR);
+    let mut pages = vec![Generated {
+        key: String::from(TAGS_DIR),
+        output: tags_path.join("index.html"),
+        type_str: TAGS_TYPE_STR,
+        model: serde_json::to_value(TagsModel {
+            name: String::from(TAGS_DIR),
+            parents: create_parent_links(&context.base_url, &tags_path, true),
+            tags: tags
+                .values()
+                .map(|(name, notes)| TagSummary {
+                    name: name.clone(),
+                    url: tag_url(&context.base_url, name),
+                    count: notes.len(),
+                })
+                .collect(),
+        })
+        .expect("Could not serialize tags"),
+    }];
+    for (slug, (name, notes)) in tags.iter() {
+        let tag_path = Path::new(TAGS_DIR).join(slug);
+        let model = TagModel {
+            name: name.clone(),
+            parents: create_parent_links(&context.base_url, &tag_path, true),
+            notes: notes.iter().map(|note| note.link()).collect(),
+        };
+        pages.push(Generated {
+            key: format!("{}/{}", TAGS_DIR, slug),
+            output: tag_path.join("index.html"),
+            type_str: TAG_TYPE_STR,
+            model: serde_json::to_value(model).expect("Could not serialize tag"),
+        });
+    }
+    pages
+}
+
+#[derive(Serialize)]
+struct TagsModel {
+    name: String,
+    parents: Vec<Link>,
+    tags: Vec<TagSummary>,
+}
+
+#[derive(Serialize)]
+struct TagSummary {
+    name: String,
+    url: String,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct TagModel {
+    name: String,
+    parents: Vec<Link>,
+    notes: Vec<NoteLink>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::TestSite;
+    use crate::Builder;
+
+    // Tag urls only lead somewhere once the layouts for tag pages are added
+    #[test]
+    fn tag_links_need_tag_pages() {
+        let site = TestSite::new(
+            "tag-links",
+            &[
+                (
+                    "layouts/note.hbs",
+                    "{{#each tags}}{{name}}={{url}};{{/each}}",
+                ),
+                ("notes/a.md", "---\ntags: [Rust]\n---\n# A"),
+            ],
+        );
+        let build = || Builder::new(site.source(), site.dest()).build().unwrap();
+        build();
+        assert_eq!(site.output("a.html").trim(), "Rust=;");
+        assert!(!site.dest().join("tags").exists());
+
+        site.write("layouts/tags.hbs", "");
+        site.write("layouts/tag.hbs", "");
+        build();
+        assert_eq!(site.output("a.html").trim(), "Rust=/tags/rust/;");
+        assert!(site.dest().join("tags/rust/index.html").is_file());
+    }
+}
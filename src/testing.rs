@@ -0,0 +1,56 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A site with the templates every build needs, in a temporary directory
+// that's removed again when it's dropped
+pub struct TestSite {
+    pub root: PathBuf,
+}
+
+impl TestSite {
+    pub fn new(name: &str, files: &[(&str, &str)]) -> TestSite {
+        let root = env::temp_dir().join(format!("rust-notes-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let site = TestSite { root };
+        for &(path, contents) in [
+            ("partials/header.hbs", ""),
+            ("partials/footer.hbs", ""),
+            ("layouts/note.hbs", "{{{content}}}"),
+            ("layouts/dir.hbs", "{{#each children}}{{name}} {{/each}}"),
+        ]
+        .iter()
+        .chain(files.iter())
+        {
+            site.write(path, contents);
+        }
+        fs::create_dir_all(site.source().join("notes")).unwrap();
+        site
+    }
+
+    pub fn source(&self) -> PathBuf {
+        self.root.join("site")
+    }
+
+    pub fn dest(&self) -> PathBuf {
+        self.root.join("out")
+    }
+
+    // Writes a file below the source directory
+    pub fn write<P: AsRef<Path>>(&self, path: P, contents: &str) {
+        let path = self.source().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    // Contents of a file below dest
+    pub fn output<P: AsRef<Path>>(&self, path: P) -> String {
+        fs::read_to_string(self.dest().join(path)).unwrap()
+    }
+}
+
+impl Drop for TestSite {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
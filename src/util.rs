@@ -1,60 +1,15 @@
-use std::fs::{DirEntry, ReadDir};
+use std::fs::File;
+use std::io;
+use std::io::Read;
 use std::path::Path;
-use std::{fs, io};
-
-// Source for walk_dir func, struct, and iterator implementation
-// copied from rust nightly source. As of now, it is unstable.
-pub fn walk_dir<P: AsRef<Path>>(path: P) -> io::Result<WalkDir> {
-    let start = fs::read_dir(path)?;
-    Ok(WalkDir {
-        cur: Some(start),
-        stack: Vec::new(),
-    })
-}
-
-pub struct WalkDir {
-    cur: Option<ReadDir>,
-    stack: Vec<io::Result<ReadDir>>,
-}
-
-impl Iterator for WalkDir {
-    type Item = io::Result<DirEntry>;
-
-    fn next(&mut self) -> Option<io::Result<DirEntry>> {
-        loop {
-            if let Some(ref mut cur) = self.cur {
-                match cur.next() {
-                    Some(Err(e)) => return Some(Err(e)),
-                    Some(Ok(next)) => {
-                        let path = next.path();
-                        let path_metadata = fs::metadata(&path)
-                            .ok()
-                            .expect("Error fetching metadata for file");
-                        if path_metadata.is_dir() {
-                            self.stack.push(fs::read_dir(&*path));
-                        }
-                        return Some(Ok(next));
-                    }
-                    None => {}
-                }
-            }
-            self.cur = None;
-            match self.stack.pop() {
-                Some(Err(e)) => return Some(Err(e)),
-                Some(Ok(next)) => self.cur = Some(next),
-                None => return None,
-            }
-        }
-    }
-}
 
 // Source for relative_from copied from rust nightly source.
 pub trait RelativeFrom {
-    fn my_relative_from<'a, P: ?Sized + AsRef<Path>>(&'a self, base: &'a P) -> Option<&Path>;
+    fn my_relative_from<'a, P: ?Sized + AsRef<Path>>(&'a self, base: &'a P) -> Option<&'a Path>;
 }
 
 impl RelativeFrom for Path {
-    fn my_relative_from<'a, P: ?Sized + AsRef<Path>>(&'a self, base: &'a P) -> Option<&Path> {
+    fn my_relative_from<'a, P: ?Sized + AsRef<Path>>(&'a self, base: &'a P) -> Option<&'a Path> {
         iter_after(self.components(), base.as_ref().components()).map(|c| c.as_path())
     }
 }
@@ -80,3 +35,87 @@ where
         iter = iter_next;
     }
 }
+
+// Decodes %XX escapes in a url path. Invalid escapes are kept as they are.
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                result.push(byte);
+                i += 3;
+            }
+            None => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+// 64 bit FNV-1a. Only used to notice changed inputs between builds, so it
+// needs to be stable across runs rather than cryptographically strong.
+struct Hasher(u64);
+
+impl Hasher {
+    fn new() -> Hasher {
+        Hasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+pub fn hash_str(contents: &str) -> String {
+    let mut hasher = Hasher::new();
+    hasher.write(contents.as_bytes());
+    hasher.finish()
+}
+
+pub fn hash_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Hasher::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buffer[..read]);
+    }
+}
+
+// Lowercase words joined by dashes, for use in urls and ids, e.g.
+// `Rust & C++` is `rust-c`
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+
+use crate::error::{Error, Result};
+use crate::listing;
+use crate::tags::TAGS_DIR;
+use crate::util::RelativeFrom;
+use crate::{Generator, Report};
+
+// Changes gathered from one debounced batch of events. Paths are relative to
+// the source root so they can be joined onto the generator's own paths.
+struct Changes<'t> {
+    changed: BTreeSet<PathBuf>,
+    removed: BTreeSet<PathBuf>,
+    templates: bool,
+    // Configured layouts and partials directories
+    template_dirs: &'t [PathBuf],
+}
+
+impl<'t> Changes<'t> {
+    fn new(template_dirs: &'t [PathBuf]) -> Changes<'t> {
+        Changes {
+            changed: BTreeSet::new(),
+            removed: BTreeSet::new(),
+            templates: false,
+            template_dirs,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty() && !self.templates
+    }
+
+    fn add(&mut self, source: &Path, event: DebouncedEvent) {
+        match event {
+            DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => {
+                self.add_changed(source, &path)
+            }
+            DebouncedEvent::Remove(path) => self.add_removed(source, &path),
+            DebouncedEvent::Rename(from, to) => {
+                self.add_removed(source, &from);
+                self.add_changed(source, &to);
+            }
+            DebouncedEvent::Rescan => self.templates = true,
+            DebouncedEvent::Error(why, path) => {
+                eprintln!("Error watching {:?}: {}", path, why);
+            }
+            _ => (),
+        }
+    }
+
+    fn add_changed(&mut self, source: &Path, path: &Path) {
+        if let Some(relative) = self.relative(source, path) {
+            self.removed.remove(&relative);
+            self.changed.insert(relative);
+        }
+    }
+
+    fn add_removed(&mut self, source: &Path, path: &Path) {
+        if let Some(relative) = self.relative(source, path) {
+            self.changed.remove(&relative);
+            self.removed.insert(relative);
+        }
+    }
+
+    fn relative(&mut self, source: &Path, path: &Path) -> Option<PathBuf> {
+        let relative = PathBuf::from(path.my_relative_from(source)?);
+        if self
+            .template_dirs
+            .iter()
+            .any(|dir| relative.starts_with(dir))
+        {
+            self.templates = true;
+            return None;
+        }
+        match relative.components().next()?.as_os_str().to_str()? {
+            "notes" | "assets" => Some(relative),
+            _ => None,
+        }
+    }
+}
+
+// Rebuilds on every change until the process is killed. `on_change` runs
+// after each batch of changes has been written to dest.
+// Errors while rebuilding are reported and the watch carries on, so a
+// half-typed template doesn't end the session.
+pub fn watch<F: FnMut()>(generator: &mut Generator<'_>, mut on_change: F) -> Result<()> {
+    let (tx, rx) = channel();
+    let mut watcher = notify::watcher(tx, Duration::from_millis(200))?;
+    let root_source = &generator.context.root_source;
+    let source =
+        fs::canonicalize(root_source).map_err(Error::io("Could not resolve", root_source))?;
+    let templates = &generator.context.config.templates;
+    let template_dirs = vec![templates.layouts.clone(), templates.partials.clone()];
+    let watched_dirs = [Path::new("notes"), Path::new("assets")];
+    for dir in watched_dirs
+        .iter()
+        .cloned()
+        .chain(template_dirs.iter().map(|dir| dir.as_path()))
+    {
+        let path = source.join(dir);
+        if path.is_dir() {
+            watcher.watch(&path, RecursiveMode::Recursive)?;
+        }
+    }
+    println!("Watching {:?} for changes", generator.context.root_source);
+
+    while let Ok(event) = rx.recv() {
+        let mut changes = Changes::new(&template_dirs);
+        changes.add(&source, event);
+        for event in rx.try_iter() {
+            changes.add(&source, event);
+        }
+        if !changes.is_empty() {
+            let result = generator.apply(changes).and_then(|report| {
+                for warning in report.warnings.iter() {
+                    eprintln!("Warning: {}", warning);
+                }
+                report.into_result()
+            });
+            if let Err(why) = result {
+                eprintln!("Error: {}", why);
+            }
+            on_change();
+        }
+    }
+    Ok(())
+}
+
+impl<'a> Generator<'a> {
+    // Rebuilds on every change to the source until the process is killed
+    pub fn watch(&mut self) -> Result<()> {
+        watch(self, || ())
+    }
+
+    fn apply(&mut self, changes: Changes<'_>) -> Result<Report> {
+        if changes.templates {
+            println!("Templates changed, rebuilding everything");
+            self.reload_templates()?;
+            return self.build();
+        }
+        self.context.site.touch();
+
+        let mut notes_changed = false;
+        for relative in changes.removed.iter() {
+            let path = self.context.root_source.join(relative);
+            if path.exists() {
+                continue;
+            }
+            println!("Removed {:?}", relative);
+            if path.starts_with(&self.context.root_notes) {
+                notes_changed = true;
+            } else {
+                self.remove_output(&path)?;
+            }
+        }
+        for relative in changes.changed.iter() {
+            let path = self.context.root_source.join(relative);
+            if !path.exists() {
+                continue;
+            }
+            // Listing files are hidden, but change their directory's page
+            if listing::is_listing_file(&path) && path.starts_with(&self.context.root_notes) {
+                println!("Changed {:?}", relative);
+                notes_changed = true;
+                continue;
+            }
+            if self.context.is_ignored(&path) {
+                continue;
+            }
+            println!("Changed {:?}", relative);
+            if path.starts_with(&self.context.root_notes) {
+                notes_changed = true;
+            } else {
+                self.copy_assets(&self.source_paths(&path)?)?;
+            }
+        }
+        // A note can change listings, tag pages and other notes' links, so
+        // notes go through both phases of a full build. Whatever the change
+        // didn't touch is skipped as unchanged.
+        if notes_changed {
+            let paths = self.source_paths(&self.context.root_notes)?;
+            self.index_site(&paths)?;
+            self.convert_all(&paths)?;
+            self.remove_stale("notes");
+            self.remove_stale(TAGS_DIR);
+        }
+        self.save_manifest()
+    }
+
+    // Removes what was copied from an asset, or a directory of them
+    fn remove_output(&mut self, path: &Path) -> Result<()> {
+        let source = self.source_key(path);
+        self.previous.remove_tree(&source);
+        let relative = path.my_relative_from(&self.context.root_source).unwrap();
+        let dest = self.context.root_dest.join(relative);
+        if dest.is_dir() {
+            fs::remove_dir_all(&dest).map_err(Error::io("Could not remove", &dest))
+        } else if dest.exists() {
+            fs::remove_file(&dest).map_err(Error::io("Could not remove", &dest))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestSite;
+    use crate::Builder;
+
+    #[test]
+    fn listing_files_reorder_their_directory() {
+        let site = TestSite::new(
+            "watch-listing",
+            &[
+                ("rust-notes.toml", "[listing]\nsort = \"natural\""),
+                ("notes/sub/note2.md", "2"),
+                ("notes/sub/note10.md", "10"),
+            ],
+        );
+        let source = site.source();
+        let listing = source.join("notes/sub/.listing.toml");
+        let mut generator = Builder::new(&source, site.dest()).generator().unwrap();
+        generator.build().unwrap();
+        assert_eq!(site.output("sub/index.html").trim(), "note2 note10");
+
+        let template_dirs = Vec::new();
+        let mut apply = |event| {
+            let mut changes = Changes::new(&template_dirs);
+            changes.add(&source, event);
+            generator.apply(changes).unwrap().into_result().unwrap();
+        };
+        fs::write(&listing, "order = [\"note10\"]").unwrap();
+        apply(DebouncedEvent::Create(listing.clone()));
+        assert_eq!(site.output("sub/index.html").trim(), "note10 note2");
+
+        fs::write(&listing, "reverse = true").unwrap();
+        apply(DebouncedEvent::Write(listing.clone()));
+        assert_eq!(site.output("sub/index.html").trim(), "note10 note2");
+        fs::write(&listing, "").unwrap();
+        apply(DebouncedEvent::Write(listing.clone()));
+        assert_eq!(site.output("sub/index.html").trim(), "note2 note10");
+
+        fs::write(&listing, "order = [\"note10\"]").unwrap();
+        apply(DebouncedEvent::Write(listing.clone()));
+        fs::remove_file(&listing).unwrap();
+        apply(DebouncedEvent::Remove(listing.clone()));
+        assert_eq!(site.output("sub/index.html").trim(), "note2 note10");
+        assert!(!site.dest().join("sub/.listing.toml").exists());
+    }
+}